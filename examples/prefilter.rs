@@ -0,0 +1,22 @@
+//! Compares searching with and without the first-character prefilter, by
+//! timing a literal pattern against one whose first character could be almost
+//! anything.
+//!
+//! Run with `cargo run --release --example prefilter`.
+
+use grep_starter_rust::Regex;
+use std::time::Instant;
+
+fn main() {
+    let input = format!(
+        "{}dog",
+        "the quick brown fox jumps over the lazy cat ".repeat(20_000)
+    );
+
+    for pattern in ["dog", "[^#]?dog"] {
+        let regex = Regex::parse(pattern).unwrap();
+        let start = Instant::now();
+        let found = regex.find(&input).map(|found| found.range());
+        println!("{:>8}: {:?} in {:?}", pattern, found, start.elapsed());
+    }
+}
@@ -0,0 +1,44 @@
+use crate::{Flags, ParseError, Regex};
+
+/// Builds a regex with flags set, as an alternative to setting them inline.
+#[derive(Debug, Default, Clone)]
+pub struct RegexBuilder {
+    flags: Flags,
+}
+
+impl RegexBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ignore case when matching, only ASCII case unless Unicode is also
+    /// enabled.
+    pub fn case_insensitive(&mut self, yes: bool) -> &mut Self {
+        self.flags.case_insensitive = yes;
+        self
+    }
+
+    /// Let `^` and `$` match at the start and end of every line.
+    pub fn multi_line(&mut self, yes: bool) -> &mut Self {
+        self.flags.multiline = yes;
+        self
+    }
+
+    /// Let `.` match `\n`.
+    pub fn dot_matches_newline(&mut self, yes: bool) -> &mut Self {
+        self.flags.dot_matches_newline = yes;
+        self
+    }
+
+    /// Use Unicode rather than ASCII definitions of `\d`, `\w` and `\s`, and
+    /// of case when ignoring it.
+    pub fn unicode(&mut self, yes: bool) -> &mut Self {
+        self.flags.unicode = yes;
+        self
+    }
+
+    /// Parse `pattern` with the flags set so far.
+    pub fn build(&self, pattern: &str) -> Result<Regex, ParseError> {
+        Regex::parse_with_flags(pattern, self.flags)
+    }
+}
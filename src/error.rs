@@ -0,0 +1,74 @@
+use thiserror::Error;
+
+/// An error encountered while parsing a regex. Each variant records the byte
+/// offset into the pattern where the problem was found.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ParseError {
+    #[error("unterminated character class starting at offset {offset}")]
+    UnterminatedGroup { offset: usize },
+    #[error("premature end of alternation group starting at offset {offset}")]
+    UnterminatedAlternation { offset: usize },
+    #[error("unmatched closing parenthesis at offset {offset}")]
+    UnmatchedParen { offset: usize },
+    #[error("trailing backslash at offset {offset}")]
+    TrailingBackslash { offset: usize },
+    #[error("invalid escape sequence at offset {offset}")]
+    InvalidEscape { offset: usize },
+    #[error("nothing to repeat at offset {offset}")]
+    NothingToRepeat { offset: usize },
+    #[error("invalid repetition bounds at offset {offset}")]
+    InvalidRepetition { offset: usize },
+    #[error("unknown character class '[:{name}:]' at offset {offset}")]
+    UnknownClass { offset: usize, name: String },
+    #[error("unknown flag '{flag}' at offset {offset}")]
+    UnknownFlag { offset: usize, flag: char },
+    #[error("invalid group name at offset {offset}")]
+    InvalidGroupName { offset: usize },
+    #[error("duplicate group name '{name}' at offset {offset}")]
+    DuplicateGroupName { offset: usize, name: String },
+    #[error("inline flags are only supported at the start of the pattern (offset {offset})")]
+    MisplacedFlags { offset: usize },
+    #[error("expected a pattern at offset {offset}")]
+    MissingPattern { offset: usize },
+    #[error("unterminated inline flags starting at offset {offset}")]
+    UnterminatedFlags { offset: usize },
+    #[error("character range out of order at offset {offset}")]
+    InvalidRange { offset: usize },
+}
+
+impl ParseError {
+    /// The byte offset into the pattern where the problem was found.
+    pub fn offset(&self) -> usize {
+        match self {
+            ParseError::UnterminatedGroup { offset }
+            | ParseError::UnterminatedAlternation { offset }
+            | ParseError::UnmatchedParen { offset }
+            | ParseError::TrailingBackslash { offset }
+            | ParseError::InvalidEscape { offset }
+            | ParseError::NothingToRepeat { offset }
+            | ParseError::InvalidRepetition { offset }
+            | ParseError::UnknownClass { offset, .. }
+            | ParseError::UnknownFlag { offset, .. }
+            | ParseError::InvalidGroupName { offset }
+            | ParseError::DuplicateGroupName { offset, .. }
+            | ParseError::MisplacedFlags { offset }
+            | ParseError::MissingPattern { offset }
+            | ParseError::UnterminatedFlags { offset }
+            | ParseError::InvalidRange { offset } => *offset,
+        }
+    }
+}
+
+/// The error returned when matching gives up after exceeding its step budget.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("match exceeded its budget of {max_steps} steps")]
+pub struct BudgetExceeded {
+    pub max_steps: usize,
+}
+
+/// The error returned when the backtracking matcher gives up after recursing
+/// too deeply, rather than overflowing the stack. This happens when a group is
+/// repeated many times, such as `(ab)*` against a very long line.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("match recursed too deeply and gave up")]
+pub struct DepthExceeded;
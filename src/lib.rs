@@ -0,0 +1,3720 @@
+use anyhow::Result;
+use parser::Parser;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+mod builder;
+mod error;
+mod nfa;
+mod parser;
+
+pub use builder::RegexBuilder;
+pub use error::{BudgetExceeded, DepthExceeded, ParseError};
+pub use nfa::Nfa;
+
+/// A compiled regular expression.
+///
+/// The backtracking matcher gives up rather than overflow the stack on a very
+/// large pattern. Methods that can report this, such as `matches` and
+/// `try_find_iter`, return a `DepthExceeded` error, and the others panic.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Regex {
+    patterns: Vec<Pattern>,
+    group_count: usize,
+    flags: Flags,
+    /// Single-character patterns, one of which must match the first character
+    /// of any match, or `None` if a match could start with anything.
+    first_chars: Option<Vec<Pattern>>,
+    /// The regex compiled to an NFA, or `None` if it can't be.
+    nfa: Option<Nfa>,
+}
+
+/// Options that change how a regex matches, which can also be set inline at
+/// the start of a pattern, as in `(?im)`.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Flags {
+    /// Ignore case when matching (`i`). Only ASCII case is ignored unless
+    /// `unicode` is also set.
+    pub case_insensitive: bool,
+    /// Let `^` and `$` match at the start and end of every line (`m`).
+    pub multiline: bool,
+    /// Let `.` match `\n` (`s`).
+    pub dot_matches_newline: bool,
+    /// Use Unicode rather than ASCII definitions of `\d`, `\w` and `\s`, and
+    /// of case when ignoring it (`u`).
+    pub unicode: bool,
+}
+
+impl Flags {
+    /// Whether a single-character pattern matches `ch`, respecting the flags.
+    pub(crate) fn matches_char(&self, pattern: &Pattern, ch: char) -> bool {
+        if *pattern == Pattern::Wildcard && self.dot_matches_newline {
+            return true;
+        }
+        let unicode = self.unicode;
+        if !self.case_insensitive {
+            return pattern.matches_with(ch, unicode);
+        }
+        let variants = case_variants(ch, unicode);
+        match pattern {
+            Pattern::NegativeGroup(chars) => variants
+                .iter()
+                .all(|&variant| !group_contains(chars, variant, unicode)),
+            Pattern::Character(c) if unicode => fold_case(*c) == fold_case(ch),
+            pattern => variants
+                .iter()
+                .any(|&variant| pattern.matches_with(variant, unicode)),
+        }
+    }
+
+    /// Whether `^` matches after the character `previous`, where `None` is
+    /// the start of the input.
+    pub(crate) fn at_start(&self, previous: Option<char>) -> bool {
+        previous.is_none() || (self.multiline && previous == Some('\n'))
+    }
+
+    /// Whether `$` matches before the character `next`, where `None` is the
+    /// end of the input.
+    pub(crate) fn at_end(&self, next: Option<char>) -> bool {
+        next.is_none() || (self.multiline && next == Some('\n'))
+    }
+}
+
+/// A single match of a regex, borrowing the text it was found in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Match<'t> {
+    text: &'t str,
+    start: usize,
+    end: usize,
+}
+
+impl<'t> Match<'t> {
+    fn new(text: &'t str, start: usize, end: usize) -> Self {
+        Match { text, start, end }
+    }
+
+    /// The byte offset of the start of the match.
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// The byte offset just past the end of the match.
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    /// The start and end byte offsets of the match.
+    pub fn range(&self) -> std::ops::Range<usize> {
+        self.start..self.end
+    }
+
+    /// The matched text.
+    pub fn as_str(&self) -> &'t str {
+        &self.text[self.start..self.end]
+    }
+}
+
+/// How many bytes of stack the backtracking matcher may use before giving up
+/// on a match, which leaves room to spare within the 2 MiB that threads are
+/// given by default.
+const MAX_STACK_USE: usize = 1 << 20;
+
+/// The address of the current stack frame, for measuring how much stack the
+/// matcher has used.
+fn stack_address() -> usize {
+    let marker = 0u8;
+    std::hint::black_box(&marker) as *const u8 as usize
+}
+
+/// State threaded through the matcher during a single match.
+struct State {
+    /// The start and end byte offsets of each capture group, where the overall
+    /// match is group 0.
+    captures: Vec<Option<(usize, usize)>>,
+    /// The address just past the top-level patterns whose failures are being
+    /// memoized, if any. Only suffixes of those patterns keep their identity
+    /// for the whole match.
+    memo_end: Option<usize>,
+    /// The remaining pattern lengths and input offsets already known not to
+    /// match.
+    failed: HashSet<(usize, usize)>,
+    /// The nearest end offset of a match recorded by `Pattern::RecordEnd`.
+    shortest_end: Option<usize>,
+    /// The furthest end offset of a match recorded by `Pattern::RecordEnd`.
+    longest_end: Option<usize>,
+    /// The ends recorded by `Pattern::CollectEnd`, with a list for each
+    /// repetition being expanded, innermost last.
+    collected: Vec<Vec<Ending>>,
+    /// How many times `match_here` has been called.
+    steps: usize,
+    /// The number of steps after which matching gives up, if limited.
+    max_steps: Option<usize>,
+    /// The stack address when matching began.
+    stack_base: usize,
+    /// Whether matching gave up after using more than `MAX_STACK_USE` bytes
+    /// of stack.
+    too_deep: bool,
+}
+
+impl State {
+    fn new(group_count: usize) -> Self {
+        State {
+            captures: vec![None; group_count + 1],
+            memo_end: None,
+            failed: HashSet::new(),
+            shortest_end: None,
+            longest_end: None,
+            collected: Vec::new(),
+            steps: 0,
+            max_steps: None,
+            stack_base: stack_address(),
+            too_deep: false,
+        }
+    }
+
+    /// Count a step, returning whether the step budget has been exceeded or
+    /// matching has recursed too deeply to go on.
+    fn step(&mut self) -> bool {
+        self.steps += 1;
+        if stack_address().abs_diff(self.stack_base) > MAX_STACK_USE {
+            self.too_deep = true;
+        }
+        self.too_deep
+            || self
+                .max_steps
+                .is_some_and(|max_steps| self.steps > max_steps)
+    }
+
+    /// Remember which suffixes of `patterns` fail to match at which positions.
+    /// This is only sound if the patterns contain no backreferences, as
+    /// otherwise whether they match also depends on the captures.
+    fn memoize(&mut self, patterns: &[Pattern]) {
+        self.memo_end = Some(patterns.as_ptr_range().end as usize);
+    }
+
+    /// The memo key for matching `next` at byte offset `pos`, if its patterns
+    /// are a suffix of the memoized patterns with nothing after them.
+    fn memo_key(&self, next: &Continuation, pos: usize) -> Option<(usize, usize)> {
+        let patterns = next.patterns;
+        (next.then.is_none() && self.memo_end? == patterns.as_ptr_range().end as usize)
+            .then_some((patterns.len(), pos))
+    }
+
+    /// A `DepthExceeded` error if matching gave up after recursing too deeply.
+    fn check_depth(&self) -> Result<()> {
+        if self.too_deep {
+            return Err(DepthExceeded.into());
+        }
+        Ok(())
+    }
+
+    /// The start and end byte offsets of a capture group, if it matched.
+    fn span(&self, index: usize) -> Option<(usize, usize)> {
+        self.captures[index]
+    }
+}
+
+/// The value of a matcher result that can only be an error if matching gave
+/// up, for methods with no way to report that other than panicking.
+fn or_give_up<T>(result: Result<T>) -> T {
+    result.unwrap_or_else(|error| panic!("{}", error))
+}
+
+/// One way of matching part of the input, as the byte offset where it ends and
+/// the captures at that point.
+#[derive(PartialEq)]
+struct Ending {
+    pos: usize,
+    captures: Vec<Option<(usize, usize)>>,
+}
+
+/// A repetition being expanded by `match_repeat_sequence`, which is kept on an
+/// explicit stack rather than recursing for each one.
+struct Repetition {
+    /// The byte offset where the repetitions so far end.
+    pos: usize,
+    /// The number of repetitions so far.
+    count: usize,
+    /// The captures after the repetitions so far.
+    captures: Vec<Option<(usize, usize)>>,
+    /// The ways of matching one more repetition that are left to try, with the
+    /// next one last.
+    ends: Vec<Ending>,
+}
+
+/// The patterns left to match, as a slice followed by whatever comes after it,
+/// so that a nested pattern can be followed by the rest of the regex without
+/// copying them into a new vector.
+#[derive(Clone, Copy)]
+struct Continuation<'a> {
+    patterns: &'a [Pattern],
+    then: Option<&'a Continuation<'a>>,
+}
+
+impl<'a> Continuation<'a> {
+    fn new(patterns: &'a [Pattern]) -> Self {
+        Continuation {
+            patterns,
+            then: None,
+        }
+    }
+
+    /// The same continuation with its slice replaced by `patterns`.
+    fn with_patterns(self, patterns: &'a [Pattern]) -> Self {
+        Continuation { patterns, ..self }
+    }
+}
+
+/// The character ending at byte offset `pos` of `input`, if any.
+fn previous_char(input: &str, pos: usize) -> Option<char> {
+    input[..pos].chars().next_back()
+}
+
+/// The character starting at byte offset `pos` of `input`, if any.
+fn next_char(input: &str, pos: usize) -> Option<char> {
+    input[pos..].chars().next()
+}
+
+/// Whether byte offset `pos` of `input` lies between a word character and a
+/// non-word character (or the start or end of the input).
+fn at_word_boundary(input: &str, pos: usize, unicode: bool) -> bool {
+    is_word_boundary(previous_char(input, pos), next_char(input, pos), unicode)
+}
+
+/// Whether there's a word boundary between the characters `previous` and
+/// `next`, where `None` is the start or end of the input. Word characters are
+/// those matched by `\w` if `unicode` is set, and ASCII ones otherwise.
+fn is_word_boundary(previous: Option<char>, next: Option<char>, unicode: bool) -> bool {
+    let is_word = |ch: Option<char>| ch.is_some_and(|ch| ch == '_' || is_alphanumeric(ch, unicode));
+    is_word(previous) != is_word(next)
+}
+
+/// A single element of a regular expression.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Pattern {
+    Character(char),
+    Digit,
+    NonDigit,
+    Alphanumeric,
+    NonAlphanumeric,
+    Whitespace,
+    NonWhitespace,
+    PositiveGroup(String),
+    NegativeGroup(String),
+    Start,
+    End,
+    /// The very start of the input, `\A`.
+    StringStart,
+    /// The very end of the input, `\z`.
+    StringEnd,
+    WordBoundary,
+    NonWordBoundary,
+    OneOrMore(Box<Pattern>),
+    ZeroOrOne(Box<Pattern>),
+    ZeroOrMore(Box<Pattern>),
+    Repeat {
+        inner: Box<Pattern>,
+        min: usize,
+        max: Option<usize>,
+    },
+    /// A quantified pattern that never gives back what it matched, such as
+    /// `a++`.
+    Possessive(Box<Pattern>),
+    /// A quantified pattern that matches as few repetitions as it can, such
+    /// as `a+?`.
+    Lazy(Box<Pattern>),
+    Wildcard,
+    Alternation(Vec<Vec<Pattern>>),
+    /// Any one of the given characters, which is how an alternation of single
+    /// characters such as `a|b|c` is parsed, to match it as cheaply as `[abc]`.
+    AnyOf(Vec<char>),
+    /// A capture group with its number and optional name.
+    Group(usize, Option<String>, Box<Pattern>),
+    Backreference(usize),
+    /// Marks where a capture group ends, inserted while matching a `Group`.
+    GroupEnd(usize),
+    /// Records where a match ends along with its captures and then fails, so
+    /// that every way of matching is tried, inserted while repeating a pattern
+    /// to find each way that one more repetition can match.
+    CollectEnd,
+    /// Records where a match ends and then fails, so that every way of
+    /// matching is tried, inserted while finding the shortest or longest
+    /// match.
+    RecordEnd,
+}
+
+impl Pattern {
+    /// Parse a single pattern from the start of `input`, returning the unparsed
+    /// remainder along with it.
+    pub fn parse(input: &str) -> Result<(&str, Self), ParseError> {
+        let mut parser = Parser::new(input);
+        let pattern = parser.parse_pattern()?;
+        Ok((parser.rest(), pattern))
+    }
+
+    /// The repeated pattern and the minimum and maximum number of repetitions
+    /// of a quantifier, or `None` if this isn't one.
+    fn repetition(&self) -> Option<(&Pattern, (usize, Option<usize>))> {
+        match self {
+            Pattern::OneOrMore(inner) => Some((inner, (1, None))),
+            Pattern::ZeroOrOne(inner) => Some((inner, (0, Some(1)))),
+            Pattern::ZeroOrMore(inner) => Some((inner, (0, None))),
+            Pattern::Repeat { inner, min, max } => Some((inner, (*min, *max))),
+            _ => None,
+        }
+    }
+
+    /// Whether this pattern always matches exactly one character.
+    fn is_single_char(&self) -> bool {
+        matches!(
+            self,
+            Pattern::Character(_)
+                | Pattern::Digit
+                | Pattern::NonDigit
+                | Pattern::Alphanumeric
+                | Pattern::NonAlphanumeric
+                | Pattern::Whitespace
+                | Pattern::NonWhitespace
+                | Pattern::PositiveGroup(_)
+                | Pattern::NegativeGroup(_)
+                | Pattern::AnyOf(_)
+                | Pattern::Wildcard
+        )
+    }
+
+    /// Whether this pattern matches the single character `ch`. Patterns that
+    /// don't always match exactly one character, such as groups, quantifiers
+    /// and anchors, never do.
+    pub fn matches(&self, ch: char) -> bool {
+        self.matches_with(ch, false)
+    }
+
+    /// Like `matches`, but with Unicode rather than ASCII definitions of the
+    /// shorthand classes if `unicode` is set.
+    fn matches_with(&self, ch: char, unicode: bool) -> bool {
+        match self {
+            Pattern::Character(c) => *c == ch,
+            Pattern::Digit => is_digit(ch, unicode),
+            Pattern::NonDigit => !is_digit(ch, unicode),
+            Pattern::Alphanumeric => is_alphanumeric(ch, unicode),
+            Pattern::NonAlphanumeric => !is_alphanumeric(ch, unicode),
+            Pattern::Whitespace => is_whitespace(ch, unicode),
+            Pattern::NonWhitespace => !is_whitespace(ch, unicode),
+            Pattern::PositiveGroup(chars) => group_contains(chars, ch, unicode),
+            Pattern::NegativeGroup(chars) => !group_contains(chars, ch, unicode),
+            Pattern::AnyOf(chars) => chars.contains(&ch),
+            Pattern::Wildcard => ch != '\n',
+            _ => false,
+        }
+    }
+}
+
+/// Whether `ch` is matched by `\d`.
+fn is_digit(ch: char, unicode: bool) -> bool {
+    if unicode {
+        ch.is_numeric()
+    } else {
+        ch.is_ascii_digit()
+    }
+}
+
+/// Whether `ch` is matched by `\w`.
+fn is_alphanumeric(ch: char, unicode: bool) -> bool {
+    if unicode {
+        ch.is_alphanumeric() || ch == '_'
+    } else {
+        ch.is_ascii_alphanumeric()
+    }
+}
+
+/// Whether `ch` is matched by `\s`.
+fn is_whitespace(ch: char, unicode: bool) -> bool {
+    if unicode {
+        ch.is_whitespace()
+    } else {
+        ch.is_ascii_whitespace()
+    }
+}
+
+impl fmt::Display for Pattern {
+    /// Write the pattern in regex syntax, such that parsing it again gives the
+    /// same pattern.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pattern::Character('\n') => write!(f, "\\n"),
+            Pattern::Character('\t') => write!(f, "\\t"),
+            Pattern::Character('\r') => write!(f, "\\r"),
+            Pattern::Character('\0') => write!(f, "\\0"),
+            Pattern::Character(ch) if "\\^$.|?*+()[]{}".contains(*ch) => write!(f, "\\{}", ch),
+            Pattern::Character(ch) => write!(f, "{}", ch),
+            Pattern::Digit => write!(f, "\\d"),
+            Pattern::NonDigit => write!(f, "\\D"),
+            Pattern::Alphanumeric => write!(f, "\\w"),
+            Pattern::NonAlphanumeric => write!(f, "\\W"),
+            Pattern::Whitespace => write!(f, "\\s"),
+            Pattern::NonWhitespace => write!(f, "\\S"),
+            Pattern::PositiveGroup(chars) => write!(f, "[{}]", chars),
+            Pattern::NegativeGroup(chars) => write!(f, "[^{}]", chars),
+            Pattern::Start => write!(f, "^"),
+            Pattern::StringStart => write!(f, "\\A"),
+            Pattern::StringEnd => write!(f, "\\z"),
+            Pattern::End => write!(f, "$"),
+            Pattern::WordBoundary => write!(f, "\\b"),
+            Pattern::NonWordBoundary => write!(f, "\\B"),
+            Pattern::OneOrMore(inner) => write!(f, "{}+", inner),
+            Pattern::ZeroOrOne(inner) => write!(f, "{}?", inner),
+            Pattern::ZeroOrMore(inner) => write!(f, "{}*", inner),
+            Pattern::Repeat { inner, min, max } => match max {
+                Some(max) if max == min => write!(f, "{}{{{}}}", inner, min),
+                Some(max) => write!(f, "{}{{{},{}}}", inner, min, max),
+                None => write!(f, "{}{{{},}}", inner, min),
+            },
+            Pattern::Possessive(inner) => write!(f, "{}+", inner),
+            Pattern::Lazy(inner) => write!(f, "{}?", inner),
+            Pattern::Wildcard => write!(f, "."),
+            Pattern::Alternation(_) | Pattern::AnyOf(_) => {
+                write!(f, "(?:")?;
+                self.fmt_alternatives(f)?;
+                write!(f, ")")
+            }
+            Pattern::Group(_, name, inner) => {
+                match name {
+                    Some(name) => write!(f, "(?<{}>", name)?,
+                    None => write!(f, "(")?,
+                }
+                inner.fmt_alternatives(f)?;
+                write!(f, ")")
+            }
+            Pattern::Backreference(index) => write!(f, "\\{}", index),
+            Pattern::GroupEnd(_) | Pattern::CollectEnd | Pattern::RecordEnd => Ok(()),
+        }
+    }
+}
+
+impl Pattern {
+    /// Write an alternation without the `(?:...)` around it, which is only
+    /// needed where it isn't the whole of a group or regex. Any other pattern
+    /// is written as usual.
+    fn fmt_alternatives(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pattern::Alternation(alternatives) => {
+                for (i, alternative) in alternatives.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "|")?;
+                    }
+                    for pattern in alternative {
+                        write!(f, "{}", pattern)?;
+                    }
+                }
+                Ok(())
+            }
+            Pattern::AnyOf(chars) => {
+                for (i, ch) in chars.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "|")?;
+                    }
+                    write!(f, "{}", Pattern::Character(*ch))?;
+                }
+                Ok(())
+            }
+            pattern => write!(f, "{}", pattern),
+        }
+    }
+}
+
+/// Split the first character off `input`, if there is one.
+fn split_first_char(input: &str) -> Option<(char, &str)> {
+    let ch = input.chars().next()?;
+    Some((ch, &input[ch.len_utf8()..]))
+}
+
+/// A single item of a character group.
+enum ClassItem {
+    Character(char),
+    Range(char, char),
+    /// A shorthand class such as `\d`, as its single-character pattern.
+    Class(Pattern),
+    /// A POSIX class such as `[:digit:]`.
+    Posix(fn(&char) -> bool),
+}
+
+/// The predicate for a POSIX character class, given its name.
+fn posix_class(name: &str) -> Option<fn(&char) -> bool> {
+    match name {
+        "alpha" => Some(char::is_ascii_alphabetic),
+        "digit" => Some(char::is_ascii_digit),
+        "alnum" => Some(char::is_ascii_alphanumeric),
+        "space" => Some(char::is_ascii_whitespace),
+        "upper" => Some(char::is_ascii_uppercase),
+        "lower" => Some(char::is_ascii_lowercase),
+        "punct" => Some(char::is_ascii_punctuation),
+        "xdigit" => Some(char::is_ascii_hexdigit),
+        _ => None,
+    }
+}
+
+/// Decode the escape `\` followed by `ch` as a single character, where `rest`
+/// is the pattern after `ch`. Returns the character along with the length in
+/// bytes of `rest` that the escape also takes, such as the digits of `\x41`, or
+/// `None` if it's malformed. Any other escaped character is taken literally.
+pub(crate) fn escaped_char(ch: char, rest: &str) -> Option<(char, usize)> {
+    let ch = match ch {
+        // There's no group 0 to refer back to, so `\0` is a NUL
+        '0' => '\0',
+        'n' => '\n',
+        't' => '\t',
+        'r' => '\r',
+        'x' => {
+            // Exactly two hex digits
+            let digits = rest.get(.."HH".len())?;
+            if !digits.chars().all(|ch| ch.is_ascii_hexdigit()) {
+                return None;
+            }
+            let value = u8::from_str_radix(digits, 16).ok()?;
+            return Some((char::from(value), digits.len()));
+        }
+        'u' => {
+            // The hex code of a Unicode scalar value, in braces
+            let digits = rest
+                .strip_prefix('{')
+                .and_then(|rest| rest.split_once('}'))
+                .map(|(digits, _)| digits)?;
+            if digits.is_empty() || !digits.chars().all(|ch| ch.is_ascii_hexdigit()) {
+                return None;
+            }
+            let ch = u32::from_str_radix(digits, 16)
+                .ok()
+                .and_then(char::from_u32)?;
+            return Some((ch, "{".len() + digits.len() + "}".len()));
+        }
+        ch => ch,
+    };
+    Some((ch, 0))
+}
+
+/// Parse the next item of a character group, where `a-z` denotes a range and a
+/// `-` at the start or end of the group is a literal.
+fn next_class_item(chars: &mut std::str::Chars) -> Option<ClassItem> {
+    let start = match next_class_atom(chars)? {
+        ClassItem::Character(start) => start,
+        item => return Some(item),
+    };
+    let mut lookahead = chars.clone();
+    if lookahead.next() == Some('-') {
+        if let Some(ClassItem::Character(end)) = next_class_atom(&mut lookahead) {
+            *chars = lookahead;
+            return Some(ClassItem::Range(start, end));
+        }
+    }
+    Some(ClassItem::Character(start))
+}
+
+/// Parse a single, possibly escaped, character or shorthand class of a
+/// character group.
+fn next_class_atom(chars: &mut std::str::Chars) -> Option<ClassItem> {
+    let item = match chars.next()? {
+        '\\' => match chars.next()? {
+            'd' => ClassItem::Class(Pattern::Digit),
+            'D' => ClassItem::Class(Pattern::NonDigit),
+            'w' => ClassItem::Class(Pattern::Alphanumeric),
+            'W' => ClassItem::Class(Pattern::NonAlphanumeric),
+            's' => ClassItem::Class(Pattern::Whitespace),
+            'S' => ClassItem::Class(Pattern::NonWhitespace),
+            ch => {
+                // Escapes were checked when the group was parsed
+                let (ch, len) = escaped_char(ch, chars.as_str())?;
+                *chars = chars.as_str()[len..].chars();
+                ClassItem::Character(ch)
+            }
+        },
+        '[' if chars.as_str().starts_with(':') => {
+            let rest = &chars.as_str()[":".len()..];
+            match rest
+                .find(":]")
+                .and_then(|len| Some((len, posix_class(&rest[..len])?)))
+            {
+                Some((len, predicate)) => {
+                    *chars = rest[len + ":]".len()..].chars();
+                    ClassItem::Posix(predicate)
+                }
+                None => ClassItem::Character('['),
+            }
+        }
+        ch => ClassItem::Character(ch),
+    };
+    Some(item)
+}
+
+/// The characters that `ch` matches when ignoring case: its lowercase and
+/// uppercase forms, and with `unicode` its case folded form, which catches
+/// folds such as `ς` to `σ` that neither of the others does.
+fn case_variants(ch: char, unicode: bool) -> [char; 3] {
+    if !unicode {
+        let lower = ch.to_ascii_lowercase();
+        return [lower, ch.to_ascii_uppercase(), lower];
+    }
+    [
+        single_char(ch.to_lowercase()).unwrap_or(ch),
+        single_char(ch.to_uppercase()).unwrap_or(ch),
+        fold_case(ch),
+    ]
+}
+
+/// Fold the case of `ch` by lowercasing its uppercase form. Characters whose
+/// case mappings aren't a single character, such as `ß`, are left alone.
+fn fold_case(ch: char) -> char {
+    single_char(ch.to_uppercase())
+        .and_then(|upper| single_char(upper.to_lowercase()))
+        .unwrap_or(ch)
+}
+
+/// The length in bytes of the start of `input` that matches `text` once the
+/// case of both is folded, if any of it does.
+fn folded_prefix_len(input: &str, text: &str) -> Option<usize> {
+    let mut chars = input.char_indices();
+    for expected in text.chars() {
+        let (_, ch) = chars.next()?;
+        if fold_case(ch) != fold_case(expected) {
+            return None;
+        }
+    }
+    Some(chars.next().map_or(input.len(), |(i, _)| i))
+}
+
+/// The only character of `chars`, if it has exactly one.
+fn single_char(mut chars: impl Iterator<Item = char>) -> Option<char> {
+    let ch = chars.next()?;
+    chars.next().is_none().then_some(ch)
+}
+
+/// Whether a character group contains `ch`.
+fn group_contains(group: &str, ch: char, unicode: bool) -> bool {
+    let mut chars = group.chars();
+    while let Some(item) = next_class_item(&mut chars) {
+        let contains = match item {
+            ClassItem::Character(c) => c == ch,
+            ClassItem::Range(start, end) => (start..=end).contains(&ch),
+            ClassItem::Class(pattern) => pattern.matches_with(ch, unicode),
+            ClassItem::Posix(predicate) => predicate(&ch),
+        };
+        if contains {
+            return true;
+        }
+    }
+    false
+}
+
+/// Whether any of `patterns` is a backreference, including within groups and
+/// quantifiers.
+fn has_backreference(patterns: &[Pattern]) -> bool {
+    patterns.iter().any(|pattern| match pattern {
+        Pattern::Backreference(_) => true,
+        Pattern::Alternation(alternatives) => alternatives
+            .iter()
+            .any(|alternative| has_backreference(alternative)),
+        Pattern::Group(_, _, inner)
+        | Pattern::OneOrMore(inner)
+        | Pattern::ZeroOrOne(inner)
+        | Pattern::ZeroOrMore(inner)
+        | Pattern::Repeat { inner, .. }
+        | Pattern::Possessive(inner)
+        | Pattern::Lazy(inner) => has_backreference(std::slice::from_ref(inner)),
+        _ => false,
+    })
+}
+
+impl Regex {
+    /// Parse `input` as a regex. As in grep, an empty pattern matches every
+    /// input, including the empty string.
+    pub fn parse(input: &str) -> Result<Self, ParseError> {
+        Self::parse_with_flags(input, Flags::default())
+    }
+
+    /// Parse a regex with the given flags, in addition to any set inline.
+    pub fn parse_with_flags(input: &str, mut flags: Flags) -> Result<Self, ParseError> {
+        let mut parser = Parser::new(input);
+        parser.parse_flags(&mut flags)?;
+        let mut patterns = parser.parse_regex()?;
+        let mut group_count = 0;
+        Self::number_groups(&mut patterns, &mut group_count);
+        Ok(Self::new(patterns, group_count, flags))
+    }
+
+    /// A regex matching `patterns`, which have already had their groups
+    /// numbered.
+    fn new(patterns: Vec<Pattern>, group_count: usize, flags: Flags) -> Self {
+        let first_chars = match Self::first_chars(&patterns) {
+            Some((first_chars, false)) => Some(first_chars),
+            _ => None,
+        };
+        let nfa = Nfa::compile(&patterns, flags);
+        Regex {
+            patterns,
+            group_count,
+            flags,
+            first_chars,
+            nfa,
+        }
+    }
+
+    /// A regex matching `input` literally, with no characters treated as
+    /// special.
+    pub fn literal(input: &str) -> Self {
+        let patterns = input.chars().map(Pattern::Character).collect();
+        Self::new(patterns, 0, Flags::default())
+    }
+
+    /// Require matches to start and end at word boundaries, as if the whole
+    /// pattern were wrapped in `\b...\b`.
+    pub fn whole_word(mut self) -> Self {
+        self.patterns.insert(0, Pattern::WordBoundary);
+        self.patterns.push(Pattern::WordBoundary);
+        Self::new(self.patterns, self.group_count, self.flags)
+    }
+
+    /// Require matches to span a whole line, as if the whole pattern were
+    /// wrapped in `^...$`.
+    pub fn whole_line(mut self) -> Self {
+        self.patterns.insert(0, Pattern::Start);
+        self.patterns.push(Pattern::End);
+        Self::new(self.patterns, self.group_count, self.flags)
+    }
+
+    /// Whether the regex begins with `^` or `\A`, so that it can only match at
+    /// the start of the input (or of a line, in multiline mode).
+    pub fn is_anchored_start(&self) -> bool {
+        matches!(
+            self.patterns.first(),
+            Some(Pattern::Start | Pattern::StringStart)
+        )
+    }
+
+    /// Whether the regex ends with `$` or `\z`, so that it can only match at
+    /// the end of the input (or of a line, in multiline mode).
+    pub fn is_anchored_end(&self) -> bool {
+        matches!(
+            self.patterns.last(),
+            Some(Pattern::End | Pattern::StringEnd)
+        )
+    }
+
+    /// The single-character patterns that can match the first character
+    /// consumed by `patterns`, along with whether they can match without
+    /// consuming anything, or `None` if that can't be determined.
+    fn first_chars(patterns: &[Pattern]) -> Option<(Vec<Pattern>, bool)> {
+        let mut first_chars = Vec::new();
+        for pattern in patterns {
+            let (chars, can_be_empty) = match pattern {
+                pattern if pattern.is_single_char() => (vec![pattern.clone()], false),
+                Pattern::OneOrMore(inner)
+                | Pattern::Group(_, _, inner)
+                | Pattern::Possessive(inner)
+                | Pattern::Lazy(inner) => Self::first_chars(std::slice::from_ref(inner))?,
+                Pattern::ZeroOrOne(inner) | Pattern::ZeroOrMore(inner) => {
+                    (Self::first_chars(std::slice::from_ref(inner))?.0, true)
+                }
+                Pattern::Repeat { inner, min, .. } => {
+                    let (chars, can_be_empty) = Self::first_chars(std::slice::from_ref(inner))?;
+                    (chars, can_be_empty || *min == 0)
+                }
+                Pattern::Alternation(alternatives) => {
+                    let mut chars = Vec::new();
+                    let mut can_be_empty = false;
+                    for alternative in alternatives {
+                        let (alternative_chars, alternative_can_be_empty) =
+                            Self::first_chars(alternative)?;
+                        chars.extend(alternative_chars);
+                        can_be_empty |= alternative_can_be_empty;
+                    }
+                    (chars, can_be_empty)
+                }
+                Pattern::Backreference(_) => return None,
+                // Anchors and markers are zero-width
+                _ => (Vec::new(), true),
+            };
+            for ch in chars {
+                if !first_chars.contains(&ch) {
+                    first_chars.push(ch);
+                }
+            }
+            if !can_be_empty {
+                return Some((first_chars, false));
+            }
+        }
+        Some((first_chars, true))
+    }
+
+    /// Number capture groups from 1 in order of their opening parentheses.
+    fn number_groups(patterns: &mut [Pattern], group_count: &mut usize) {
+        for pattern in patterns {
+            match pattern {
+                Pattern::Group(index, _, inner_pattern) => {
+                    *group_count += 1;
+                    *index = *group_count;
+                    Self::number_groups(std::slice::from_mut(inner_pattern), group_count);
+                }
+                Pattern::Alternation(alternatives) => {
+                    for alternative in alternatives {
+                        Self::number_groups(alternative, group_count);
+                    }
+                }
+                Pattern::OneOrMore(inner_pattern)
+                | Pattern::ZeroOrOne(inner_pattern)
+                | Pattern::ZeroOrMore(inner_pattern)
+                | Pattern::Repeat {
+                    inner: inner_pattern,
+                    ..
+                }
+                | Pattern::Possessive(inner_pattern)
+                | Pattern::Lazy(inner_pattern) => {
+                    Self::number_groups(std::slice::from_mut(inner_pattern), group_count)
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Whether the regex matches anywhere in `input`, as for `is_match`, but
+    /// returning a `DepthExceeded` error if the backtracking matcher recursed
+    /// too deeply to tell rather than reporting no match.
+    pub fn matches(&self, input: &str) -> Result<bool> {
+        if let Some(nfa) = &self.nfa {
+            return Ok(nfa.is_match(input));
+        }
+        let mut state = State::new(self.group_count);
+        let is_match = self.match_(input, 0, &self.patterns, &mut state).is_some();
+        state.check_depth()?;
+        Ok(is_match)
+    }
+
+    /// Whether the regex matches anywhere in `input`, giving up with a
+    /// `BudgetExceeded` error after `max_steps` steps of backtracking. This
+    /// always uses the backtracking matcher, so it bounds the time taken by
+    /// patterns that backtrack badly. It also gives up with a `DepthExceeded`
+    /// error if it recurses too deeply.
+    pub fn matches_with_budget(&self, input: &str, max_steps: usize) -> Result<bool> {
+        let mut state = State::new(self.group_count);
+        state.max_steps = Some(max_steps);
+        let is_match = self.match_(input, 0, &self.patterns, &mut state).is_some();
+        state.check_depth()?;
+        if state.steps > max_steps {
+            return Err(BudgetExceeded { max_steps }.into());
+        }
+        Ok(is_match)
+    }
+
+    /// Whether the regex matches anywhere in `input`. This stops at the first
+    /// match without working out its span or captures, and runs in linear time
+    /// unless the regex uses backreferences.
+    pub fn is_match(&self, input: &str) -> bool {
+        or_give_up(self.matches(input))
+    }
+
+    /// Whether the regex matches anywhere in each of `inputs`, in the same
+    /// order.
+    pub fn matches_all_of(&self, inputs: &[&str]) -> Vec<bool> {
+        match &self.nfa {
+            Some(nfa) => inputs.iter().map(|input| nfa.is_match(input)).collect(),
+            None => inputs.iter().map(|input| self.is_match(input)).collect(),
+        }
+    }
+
+    /// Whether the regex matches anywhere in `input`, treating each byte as a
+    /// character. Bytes above 0x7f are taken to be Latin-1, so no UTF-8
+    /// validation is needed.
+    pub fn matches_bytes(&self, input: &[u8]) -> bool {
+        match &self.nfa {
+            Some(nfa) => nfa.is_match_bytes(input),
+            None => self.is_match(
+                &input
+                    .iter()
+                    .map(|&byte| char::from(byte))
+                    .collect::<String>(),
+            ),
+        }
+    }
+
+    /// The regex compiled to an NFA, or `None` if it uses backreferences or
+    /// possessive quantifiers. The NFA is compiled once, when the regex is
+    /// built.
+    pub fn nfa(&self) -> Option<&Nfa> {
+        self.nfa.as_ref()
+    }
+
+    /// Whether the regex matches the whole of `input`, rather than just part
+    /// of it. The match has to span the whole input even in multiline mode,
+    /// where `^` and `$` also match around newlines.
+    pub fn matches_full(&self, input: &str) -> bool {
+        let mut patterns = self.patterns.clone();
+        patterns.push(Pattern::StringEnd);
+
+        let mut state = State::new(self.group_count);
+        let is_match = self
+            .match_here(input, 0, Continuation::new(&patterns), &mut state)
+            .is_some();
+        or_give_up(state.check_depth());
+        is_match
+    }
+
+    /// Returns the text of the overall match followed by the text captured by
+    /// each group, or `None` if there is no match.
+    pub fn captures(&self, input: &str) -> Option<Vec<Option<String>>> {
+        Some(
+            self.capture_spans(input)?
+                .into_iter()
+                .map(|span| span.map(|(start, end)| input[start..end].to_string()))
+                .collect(),
+        )
+    }
+
+    /// Returns the text captured by each named group that took part in the
+    /// match, by name, or `None` if there is no match.
+    pub fn captures_named(&self, input: &str) -> Option<HashMap<String, String>> {
+        let spans = self.capture_spans(input)?;
+        let mut names = Vec::new();
+        Self::group_names(&self.patterns, &mut names);
+        Some(
+            names
+                .into_iter()
+                .filter_map(|(index, name)| {
+                    let (start, end) = spans[index]?;
+                    Some((name.to_string(), input[start..end].to_string()))
+                })
+                .collect(),
+        )
+    }
+
+    /// Collect the number and name of each named group in `patterns`.
+    fn group_names<'a>(patterns: &'a [Pattern], names: &mut Vec<(usize, &'a str)>) {
+        for pattern in patterns {
+            match pattern {
+                Pattern::Group(index, name, inner) => {
+                    if let Some(name) = name {
+                        names.push((*index, name));
+                    }
+                    Self::group_names(std::slice::from_ref(inner), names);
+                }
+                Pattern::Alternation(alternatives) => {
+                    for alternative in alternatives {
+                        Self::group_names(alternative, names);
+                    }
+                }
+                Pattern::OneOrMore(inner)
+                | Pattern::ZeroOrOne(inner)
+                | Pattern::ZeroOrMore(inner)
+                | Pattern::Repeat { inner, .. }
+                | Pattern::Possessive(inner)
+                | Pattern::Lazy(inner) => Self::group_names(std::slice::from_ref(inner), names),
+                _ => {}
+            }
+        }
+    }
+
+    /// Returns the start and end byte offsets of the overall match followed by
+    /// those of each group, or `None` if there is no match.
+    pub fn capture_spans(&self, input: &str) -> Option<Vec<Option<(usize, usize)>>> {
+        let state = self.match_captures(input, 0)?;
+        Some(
+            (0..state.captures.len())
+                .map(|index| state.span(index))
+                .collect(),
+        )
+    }
+
+    /// Returns the leftmost match in `input`.
+    pub fn find<'t>(&self, input: &'t str) -> Option<Match<'t>> {
+        let (start, end) = self.match_captures(input, 0)?.span(0)?;
+        Some(Match::new(input, start, end))
+    }
+
+    /// Returns the match starting exactly at byte offset `pos` of `input`, if
+    /// any, without searching further along. Assertions such as `\b` still
+    /// see the input before `pos`, so this can be used to consume input a
+    /// token at a time.
+    pub fn find_at<'t>(&self, input: &'t str, pos: usize) -> Option<Match<'t>> {
+        if !input.is_char_boundary(pos) {
+            return None;
+        }
+        let mut state = State::new(self.group_count);
+        if !has_backreference(&self.patterns) {
+            state.memoize(&self.patterns);
+        }
+        state.captures[0] = Some((pos, pos));
+        let end = self.match_here(input, pos, Continuation::new(&self.patterns), &mut state);
+        or_give_up(state.check_depth());
+        Some(Match::new(input, pos, end?))
+    }
+
+    /// Returns the leftmost-longest match in `input`. Unlike `find`, which
+    /// takes the first alternative or repetition count that matches, this
+    /// tries every way of matching at the leftmost position that matches and
+    /// takes the one that ends furthest.
+    pub fn find_longest<'t>(&self, input: &'t str) -> Option<Match<'t>> {
+        let (start, _, longest_end) = self.match_ends(input)?;
+        Some(Match::new(input, start, longest_end))
+    }
+
+    /// Returns the length of the shortest match at the leftmost position that
+    /// matches. Unlike `find`, which repeats greedily, this tries every way of
+    /// matching there and takes the one that ends soonest, so `a+` matches
+    /// just one `a`.
+    pub fn shortest_match(&self, input: &str) -> Option<usize> {
+        let (start, shortest_end, _) = self.match_ends(input)?;
+        Some(shortest_end - start)
+    }
+
+    /// The leftmost start offset at which the regex matches `input`, along
+    /// with the nearest and furthest offsets that a match from there can end
+    /// at.
+    fn match_ends(&self, input: &str) -> Option<(usize, usize, usize)> {
+        if let Some(nfa) = &self.nfa {
+            return nfa.match_ends(input);
+        }
+        let mut patterns = self.patterns.clone();
+        patterns.push(Pattern::RecordEnd);
+
+        let mut state = State::new(self.group_count);
+        if !has_backreference(&patterns) {
+            state.memoize(&patterns);
+        }
+        let starts = input.char_indices().map(|(i, _)| i);
+        for start in starts.chain(std::iter::once(input.len())) {
+            self.match_here(input, start, Continuation::new(&patterns), &mut state);
+            or_give_up(state.check_depth());
+            if let (Some(shortest_end), Some(longest_end)) = (state.shortest_end, state.longest_end)
+            {
+                return Some((start, shortest_end, longest_end));
+            }
+        }
+        None
+    }
+
+    /// Returns all non-overlapping matches in `input`, from left to right.
+    pub fn find_iter<'a>(&'a self, input: &'a str) -> impl Iterator<Item = Match<'a>> + 'a {
+        self.capture_spans_iter(input)
+            .filter_map(|spans| spans[0])
+            .map(|(start, end)| Match::new(input, start, end))
+    }
+
+    /// Returns all non-overlapping matches in `input`, as for `find_iter`, but
+    /// ending with a `DepthExceeded` error if the backtracking matcher recursed
+    /// too deeply to find the next match rather than stopping early.
+    pub fn try_find_iter<'a>(
+        &'a self,
+        input: &'a str,
+    ) -> impl Iterator<Item = Result<Match<'a>>> + 'a {
+        self.try_capture_spans_iter(input).map(move |spans| {
+            let (start, end) = spans?[0].unwrap();
+            Ok(Match::new(input, start, end))
+        })
+    }
+
+    /// Returns the match starting at each position in `input` that has one,
+    /// from left to right, so unlike `find_iter` the matches may overlap. At
+    /// most one match is returned per start position.
+    pub fn find_overlapping<'a>(&'a self, input: &'a str) -> impl Iterator<Item = Match<'a>> + 'a {
+        let starts = input.char_indices().map(|(i, _)| i);
+        starts
+            .chain(std::iter::once(input.len()))
+            .filter_map(move |start| self.find_at(input, start))
+    }
+
+    /// Returns the text of the overall match and of each group for all
+    /// non-overlapping matches in `input`, from left to right, as for
+    /// `captures`.
+    pub fn captures_iter<'a, 't: 'a>(
+        &'a self,
+        input: &'t str,
+    ) -> impl Iterator<Item = Vec<Option<&'t str>>> + 'a {
+        self.capture_spans_iter(input).map(move |spans| {
+            spans
+                .into_iter()
+                .map(|span| span.map(|(start, end)| &input[start..end]))
+                .collect()
+        })
+    }
+
+    /// Returns the capture spans of all non-overlapping matches in `input`,
+    /// from left to right, as for `capture_spans`.
+    fn capture_spans_iter<'a>(
+        &'a self,
+        input: &'a str,
+    ) -> impl Iterator<Item = Vec<Option<(usize, usize)>>> + 'a {
+        self.try_capture_spans_iter(input).map(or_give_up)
+    }
+
+    /// Returns the capture spans of all non-overlapping matches in `input`, as
+    /// for `capture_spans_iter`, ending with an error if matching gives up.
+    fn try_capture_spans_iter<'a>(
+        &'a self,
+        input: &'a str,
+    ) -> impl Iterator<Item = Result<Vec<Option<(usize, usize)>>>> + 'a {
+        let mut search_start = Some(0);
+        std::iter::from_fn(move || {
+            // The search start is only put back after a match, so that an
+            // error ends the iteration
+            let state = match self.try_match_captures(input, search_start.take()?) {
+                Ok(state) => state?,
+                Err(error) => return Some(Err(error)),
+            };
+            let (start, end) = state.span(0)?;
+            search_start = if start == end {
+                // Step over an empty match so it isn't found again
+                split_first_char(&input[end..]).map(|(ch, _)| end + ch.len_utf8())
+            } else {
+                Some(end)
+            };
+            Some(Ok((0..state.captures.len())
+                .map(|index| state.span(index))
+                .collect()))
+        })
+    }
+
+    /// Split `input` into the text between non-overlapping matches. A match at
+    /// the start or end of the input gives an empty first or last field.
+    pub fn split<'a>(&'a self, input: &'a str) -> Vec<&'a str> {
+        let mut fields = Vec::new();
+        let mut last_end = 0;
+        for found in self.find_iter(input) {
+            fields.push(&input[last_end..found.start()]);
+            last_end = found.end();
+        }
+        fields.push(&input[last_end..]);
+        fields
+    }
+
+    /// Replace every non-overlapping match in `input` with `replacement`, in
+    /// which `$1` or `${name}` stands for the text captured by a group and `$$`
+    /// for a literal `$`. Groups that don't exist or didn't match give an empty
+    /// string.
+    pub fn replace_all(&self, input: &str, replacement: &str) -> String {
+        self.replacen(input, replacement, 0)
+    }
+
+    /// Replace the first `limit` non-overlapping matches in `input` with
+    /// `replacement`, or every match if `limit` is 0, as for `replace_all`.
+    pub fn replacen(&self, input: &str, replacement: &str, limit: usize) -> String {
+        let limit = if limit == 0 { usize::MAX } else { limit };
+        let mut names = Vec::new();
+        Self::group_names(&self.patterns, &mut names);
+
+        let mut output = String::with_capacity(input.len());
+        let mut last_end = 0;
+        for spans in self.capture_spans_iter(input).take(limit) {
+            let (start, end) = spans[0].unwrap();
+            output.push_str(&input[last_end..start]);
+            Self::expand(replacement, input, &spans, &names, &mut output);
+            last_end = end;
+        }
+        output.push_str(&input[last_end..]);
+        output
+    }
+
+    /// Append `replacement` to `output`, substituting group references with
+    /// the text captured in `input` at `spans`.
+    fn expand(
+        replacement: &str,
+        input: &str,
+        spans: &[Option<(usize, usize)>],
+        names: &[(usize, &str)],
+        output: &mut String,
+    ) {
+        let mut rest = replacement;
+        while let Some(dollar) = rest.find('$') {
+            output.push_str(&rest[..dollar]);
+            rest = &rest[dollar + 1..];
+
+            let digits = rest.len()
+                - rest
+                    .trim_start_matches(|ch: char| ch.is_ascii_digit())
+                    .len();
+            let group = if rest.starts_with('$') {
+                output.push('$');
+                rest = &rest[1..];
+                continue;
+            } else if digits > 0 {
+                let group = rest[..digits].parse().ok();
+                rest = &rest[digits..];
+                group
+            } else if let Some((name, remainder)) = rest
+                .strip_prefix('{')
+                .and_then(|braced| braced.split_once('}'))
+            {
+                rest = remainder;
+                name.parse().ok().or_else(|| {
+                    names
+                        .iter()
+                        .find(|(_, group_name)| *group_name == name)
+                        .map(|(index, _)| *index)
+                })
+            } else {
+                // Not a reference, so the `$` is literal
+                output.push('$');
+                continue;
+            };
+
+            if let Some(Some((start, end))) = group.and_then(|group: usize| spans.get(group)) {
+                output.push_str(&input[*start..*end]);
+            }
+        }
+        output.push_str(rest);
+    }
+
+    /// Match against `input`, starting the search at byte offset `start` and
+    /// keeping the capture state of a successful match.
+    fn match_captures(&self, input: &str, start: usize) -> Option<State> {
+        or_give_up(self.try_match_captures(input, start))
+    }
+
+    /// Like `match_captures`, but returning a `DepthExceeded` error if the
+    /// matcher recursed too deeply to tell whether there's a match.
+    fn try_match_captures(&self, input: &str, start: usize) -> Result<Option<State>> {
+        let mut state = State::new(self.group_count);
+        let end = self.match_(input, start, &self.patterns, &mut state);
+        state.check_depth()?;
+        Ok(end.map(|end| {
+            state.captures[0] = state.captures[0].map(|(start, _)| (start, end));
+            state
+        }))
+    }
+
+    /// Whether a match could start at byte offset `pos` of `input`, judging by
+    /// the character there.
+    fn could_start(&self, input: &str, pos: usize) -> bool {
+        match &self.first_chars {
+            Some(first_chars) => next_char(input, pos).is_some_and(|ch| {
+                first_chars
+                    .iter()
+                    .any(|pattern| self.flags.matches_char(pattern, ch))
+            }),
+            None => true,
+        }
+    }
+
+    /// Search `input` for a match of `patterns` starting at or after byte
+    /// offset `pos`, returning the byte offset where the match ends.
+    fn match_(
+        &self,
+        input: &str,
+        pos: usize,
+        patterns: &[Pattern],
+        state: &mut State,
+    ) -> Option<usize> {
+        if !has_backreference(patterns) {
+            state.memoize(patterns);
+        }
+
+        let anchored = match patterns.first() {
+            Some(Pattern::Start) => !self.flags.multiline,
+            Some(Pattern::StringStart) => true,
+            _ => false,
+        };
+        if anchored {
+            if pos != 0 {
+                return None;
+            }
+            state.captures[0] = Some((pos, pos));
+            return self.match_here(input, pos, Continuation::new(&patterns[1..]), state);
+        }
+
+        // Try every starting position, including the end of the input
+        let mut pos = pos;
+        loop {
+            state.captures[0] = Some((pos, pos));
+            if self.could_start(input, pos) {
+                if let Some(end) = self.match_here(input, pos, Continuation::new(patterns), state) {
+                    return Some(end);
+                }
+            }
+            pos += next_char(input, pos)?.len_utf8();
+        }
+    }
+
+    /// Match the patterns of `next` against `input` starting exactly at byte
+    /// offset `pos`, returning the byte offset where the match ends. The whole
+    /// input is kept so that assertions can look behind `pos`.
+    fn match_here(
+        &self,
+        input: &str,
+        pos: usize,
+        next: Continuation,
+        state: &mut State,
+    ) -> Option<usize> {
+        if state.step() {
+            return None;
+        }
+        let key = state.memo_key(&next, pos);
+        if key.is_some_and(|key| state.failed.contains(&key)) {
+            return None;
+        }
+        let end = self.match_here_uncached(input, pos, next, state);
+        if let (None, Some(key)) = (end, key) {
+            state.failed.insert(key);
+        }
+        end
+    }
+
+    fn match_here_uncached(
+        &self,
+        input: &str,
+        pos: usize,
+        next: Continuation,
+        state: &mut State,
+    ) -> Option<usize> {
+        let (pattern, rest) = match next.patterns.split_first() {
+            Some((pattern, patterns)) => (pattern, next.with_patterns(patterns)),
+            None => {
+                return match next.then {
+                    Some(then) => self.match_here(input, pos, *then, state),
+                    None => Some(pos),
+                }
+            }
+        };
+        match pattern {
+            Pattern::OneOrMore(inner_pattern) => {
+                self.match_repeat(input, pos, inner_pattern, (1, None), rest, state)
+            }
+            Pattern::ZeroOrOne(inner_pattern) => {
+                self.match_repeat(input, pos, inner_pattern, (0, Some(1)), rest, state)
+            }
+            Pattern::ZeroOrMore(inner_pattern) => {
+                self.match_repeat(input, pos, inner_pattern, (0, None), rest, state)
+            }
+            Pattern::Repeat { inner, min, max } => {
+                self.match_repeat(input, pos, inner, (*min, *max), rest, state)
+            }
+            Pattern::Possessive(inner_pattern) => {
+                self.match_possessive(input, pos, inner_pattern, rest, state)
+            }
+            Pattern::Lazy(quantified) => {
+                let repetition = quantified.repetition()?;
+                if repetition.0.is_single_char() {
+                    self.match_lazy(input, pos, repetition, rest, state)
+                } else {
+                    self.match_repeat_sequence(input, pos, repetition, true, rest, state)
+                }
+            }
+            Pattern::Alternation(alternatives) => {
+                self.match_alternatives(input, pos, alternatives, rest, state)
+            }
+            Pattern::Group(index, _, inner_pattern) => {
+                self.match_group(input, pos, *index, inner_pattern, rest, state)
+            }
+            Pattern::GroupEnd(index) => {
+                let saved = state.captures[*index];
+                if let Some((start, _)) = saved {
+                    state.captures[*index] = Some((start, pos));
+                }
+                let end = self.match_here(input, pos, rest, state);
+                if end.is_none() {
+                    state.captures[*index] = saved;
+                }
+                end
+            }
+            Pattern::Backreference(index) => {
+                let (start, end) = state.captures.get(*index).copied().flatten()?;
+                let captured = &input[start..end];
+                let len = if self.flags.case_insensitive && self.flags.unicode {
+                    // Characters of different case can differ in length
+                    folded_prefix_len(&input[pos..], captured)?
+                } else {
+                    let prefix = input.get(pos..pos + captured.len())?;
+                    let equal = prefix == captured
+                        || (self.flags.case_insensitive && prefix.eq_ignore_ascii_case(captured));
+                    equal.then_some(captured.len())?
+                };
+                self.match_here(input, pos + len, rest, state)
+            }
+            Pattern::RecordEnd => {
+                state.shortest_end = Some(state.shortest_end.map_or(pos, |end| end.min(pos)));
+                state.longest_end = state.longest_end.max(Some(pos));
+                None
+            }
+            Pattern::CollectEnd => {
+                let ending = Ending {
+                    pos,
+                    captures: state.captures.clone(),
+                };
+                let collected = state.collected.last_mut()?;
+                // Different ways of matching can end up in the same place
+                if !collected.contains(&ending) {
+                    collected.push(ending);
+                }
+                None
+            }
+            Pattern::WordBoundary if at_word_boundary(input, pos, self.flags.unicode) => {
+                self.match_here(input, pos, rest, state)
+            }
+            Pattern::NonWordBoundary if !at_word_boundary(input, pos, self.flags.unicode) => {
+                self.match_here(input, pos, rest, state)
+            }
+            Pattern::Start if self.flags.at_start(previous_char(input, pos)) => {
+                self.match_here(input, pos, rest, state)
+            }
+            Pattern::StringStart if pos == 0 => self.match_here(input, pos, rest, state),
+            // Zero-width, so it needs to be checked even when followed by the
+            // end of a group
+            Pattern::End if self.flags.at_end(next_char(input, pos)) => {
+                self.match_here(input, pos, rest, state)
+            }
+            Pattern::StringEnd if pos == input.len() => self.match_here(input, pos, rest, state),
+            // Zero-width patterns whose conditions don't hold
+            Pattern::WordBoundary
+            | Pattern::NonWordBoundary
+            | Pattern::Start
+            | Pattern::StringStart
+            | Pattern::End
+            | Pattern::StringEnd => None,
+            pattern => {
+                // Match a run of single characters in a loop rather than
+                // recursing for each one
+                let (mut pattern, mut pos, mut rest) = (pattern, pos, rest);
+                loop {
+                    match next_char(input, pos) {
+                        Some(ch) if self.flags.matches_char(pattern, ch) => pos += ch.len_utf8(),
+                        _ => return None,
+                    }
+                    match rest.patterns.split_first() {
+                        Some((next_pattern, patterns)) if next_pattern.is_single_char() => {
+                            pattern = next_pattern;
+                            rest = rest.with_patterns(patterns);
+                        }
+                        _ => break,
+                    }
+                }
+                self.match_here(input, pos, rest, state)
+            }
+        }
+    }
+
+    fn match_repeat(
+        &self,
+        input: &str,
+        pos: usize,
+        inner_pattern: &Pattern,
+        (min, max): (usize, Option<usize>),
+        next: Continuation,
+        state: &mut State,
+    ) -> Option<usize> {
+        if !inner_pattern.is_single_char() {
+            let repetition = (inner_pattern, (min, max));
+            return self.match_repeat_sequence(input, pos, repetition, false, next, state);
+        }
+
+        // Greedily consume as many repetitions as allowed, then backtrack one at
+        // a time down to the minimum until the following patterns match
+        let mut ends = vec![pos];
+        let mut end = pos;
+        while max != Some(ends.len() - 1) {
+            match next_char(input, end) {
+                Some(ch) if self.flags.matches_char(inner_pattern, ch) => {
+                    end += ch.len_utf8();
+                    ends.push(end);
+                }
+                _ => break,
+            }
+        }
+        ends.iter()
+            .enumerate()
+            .rev()
+            .take_while(|(count, _)| *count >= min)
+            .find_map(|(_, &end)| self.match_here(input, end, next, state))
+    }
+
+    /// Repeat a pattern that may match more than one character, such as a
+    /// group. The repetitions so far are kept on an explicit stack rather than
+    /// recursing for each one, so that many repetitions can't overflow the
+    /// stack. Greedy repetition tries another repetition before `next`, and
+    /// lazy repetition tries `next` first.
+    fn match_repeat_sequence(
+        &self,
+        input: &str,
+        pos: usize,
+        (inner_pattern, (min, max)): (&Pattern, (usize, Option<usize>)),
+        lazy: bool,
+        next: Continuation,
+        state: &mut State,
+    ) -> Option<usize> {
+        let saved = state.captures.clone();
+        // Without backreferences, whether the rest matches only depends on
+        // where the repetitions end and how many more are needed, so each of
+        // those only needs trying once
+        let memoize = state.memo_end.is_some();
+        let mut tried = HashSet::new();
+
+        let mut stack: Vec<Repetition> = Vec::new();
+        let mut entering = Some(Ending {
+            pos,
+            captures: saved.clone(),
+        });
+        loop {
+            if let Some(Ending { pos, captures }) = entering.take() {
+                let count = stack.len();
+                state.captures = captures.clone();
+                if lazy && count >= min {
+                    if let Some(end) = self.match_here(input, pos, next, state) {
+                        return Some(end);
+                    }
+                    state.captures = captures.clone();
+                }
+                let mut ends = if max == Some(count) {
+                    Vec::new()
+                } else {
+                    self.match_endings(input, pos, inner_pattern, state)
+                };
+                // An optional repetition has to consume input, otherwise a
+                // pattern that matches empty would repeat forever
+                ends.retain(|ending| count < min || ending.pos > pos);
+                ends.reverse();
+                stack.push(Repetition {
+                    pos,
+                    count,
+                    captures,
+                    ends,
+                });
+            }
+
+            let top = match stack.last_mut() {
+                Some(top) => top,
+                None => break,
+            };
+            if let Some(ending) = top.ends.pop() {
+                let count = top.count + 1;
+                let key = (
+                    ending.pos,
+                    if max.is_some() { count } else { count.min(min) },
+                );
+                if !memoize || tried.insert(key) {
+                    entering = Some(ending);
+                }
+                continue;
+            }
+
+            let top = stack.pop()?;
+            if !lazy && top.count >= min {
+                state.captures = top.captures;
+                if let Some(end) = self.match_here(input, top.pos, next, state) {
+                    return Some(end);
+                }
+            }
+        }
+        state.captures = saved;
+        None
+    }
+
+    /// Every way that `inner_pattern` can match starting at byte offset `pos`,
+    /// in the order they should be tried.
+    fn match_endings(
+        &self,
+        input: &str,
+        pos: usize,
+        inner_pattern: &Pattern,
+        state: &mut State,
+    ) -> Vec<Ending> {
+        let saved = state.captures.clone();
+        state.collected.push(Vec::new());
+        let collect = [Pattern::CollectEnd];
+        let collect = Continuation::new(&collect);
+        let inner = Continuation {
+            patterns: std::slice::from_ref(inner_pattern),
+            then: Some(&collect),
+        };
+        self.match_here(input, pos, inner, state);
+        state.captures = saved;
+        state.collected.pop().unwrap_or_default()
+    }
+
+    /// Like `match_repeat`, but try as few repetitions as allowed first and
+    /// only add more while the following patterns fail to match.
+    fn match_lazy(
+        &self,
+        input: &str,
+        pos: usize,
+        (inner_pattern, (min, max)): (&Pattern, (usize, Option<usize>)),
+        next: Continuation,
+        state: &mut State,
+    ) -> Option<usize> {
+        let mut end = pos;
+        let mut count = 0;
+        loop {
+            if count >= min {
+                if let Some(end) = self.match_here(input, end, next, state) {
+                    return Some(end);
+                }
+            }
+            if max == Some(count) {
+                return None;
+            }
+            match next_char(input, end) {
+                Some(ch) if self.flags.matches_char(inner_pattern, ch) => {
+                    end += ch.len_utf8();
+                    count += 1;
+                }
+                _ => return None,
+            }
+        }
+    }
+
+    /// Match `inner_pattern` on its own and continue with `next` from where its
+    /// first match ends, without backtracking into it if `next` fails.
+    fn match_possessive(
+        &self,
+        input: &str,
+        pos: usize,
+        inner_pattern: &Pattern,
+        next: Continuation,
+        state: &mut State,
+    ) -> Option<usize> {
+        let saved = state.captures.clone();
+        let inner = Continuation::new(std::slice::from_ref(inner_pattern));
+        let end = self
+            .match_here(input, pos, inner, state)
+            .and_then(|inner_end| self.match_here(input, inner_end, next, state));
+        if end.is_none() {
+            state.captures = saved;
+        }
+        end
+    }
+
+    /// Match each alternative followed by `next` in turn, so that an
+    /// alternative which matches but leaves the rest unable to is given up in
+    /// favour of the next one. The alternative is continued by `next` rather
+    /// than copied in front of it, so trying it allocates nothing.
+    fn match_alternatives(
+        &self,
+        input: &str,
+        pos: usize,
+        alternatives: &[Vec<Pattern>],
+        next: Continuation,
+        state: &mut State,
+    ) -> Option<usize> {
+        alternatives.iter().find_map(|alternative| {
+            let alternative = Continuation {
+                patterns: alternative,
+                then: Some(&next),
+            };
+            self.match_here(input, pos, alternative, state)
+        })
+    }
+
+    fn match_group(
+        &self,
+        input: &str,
+        pos: usize,
+        index: usize,
+        inner_pattern: &Pattern,
+        next: Continuation,
+        state: &mut State,
+    ) -> Option<usize> {
+        let saved = state.captures[index];
+        state.captures[index] = Some((pos, pos));
+
+        let group_end = [Pattern::GroupEnd(index)];
+        let end = Continuation {
+            patterns: &group_end,
+            then: Some(&next),
+        };
+        let inner = Continuation {
+            patterns: std::slice::from_ref(inner_pattern),
+            then: Some(&end),
+        };
+        let end = self.match_here(input, pos, inner, state);
+        if end.is_none() {
+            state.captures[index] = saved;
+        }
+        end
+    }
+}
+
+impl fmt::Display for Regex {
+    /// Write the regex in canonical regex syntax, which parses back to an
+    /// equivalent regex.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.flags != Flags::default() {
+            write!(f, "(?")?;
+            if self.flags.case_insensitive {
+                write!(f, "i")?;
+            }
+            if self.flags.multiline {
+                write!(f, "m")?;
+            }
+            if self.flags.dot_matches_newline {
+                write!(f, "s")?;
+            }
+            if self.flags.unicode {
+                write!(f, "u")?;
+            }
+            write!(f, ")")?;
+        }
+        match &self.patterns[..] {
+            // A group with a single alternative would parse back to that
+            // alternative alone, so it's written verbatim
+            [pattern @ Pattern::Alternation(alternatives)] if alternatives.len() == 1 => {
+                write!(f, "{}", pattern)
+            }
+            [pattern] => pattern.fmt_alternatives(f),
+            patterns => patterns
+                .iter()
+                .try_for_each(|pattern| write!(f, "{}", pattern)),
+        }
+    }
+}
+
+/// Parse `regex_str` and check whether it matches anywhere in `input_line`.
+pub fn match_pattern(input_line: &str, regex_str: &str) -> Result<bool, ParseError> {
+    let regex = Regex::parse(regex_str)?;
+    Ok(regex.is_match(input_line))
+}
+
+/// Parse `regex_str` and return the start and end byte offsets of its leftmost
+/// match in `input_line`, if any.
+pub fn match_pattern_find(
+    input_line: &str,
+    regex_str: &str,
+) -> Result<Option<(usize, usize)>, ParseError> {
+    let regex = Regex::parse(regex_str)?;
+    Ok(regex
+        .find(input_line)
+        .map(|found| (found.start(), found.end())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        match_pattern, match_pattern_find, BudgetExceeded, Continuation, DepthExceeded, Flags,
+        ParseError, Pattern, Regex, RegexBuilder, State,
+    };
+    use std::collections::HashMap;
+
+    #[test]
+    fn parse() {
+        let regex = Regex::parse("^[^abc]\\w?f+oo\\d+[bar]+(ca|d)$").unwrap();
+        assert_eq!(
+            regex,
+            Regex {
+                patterns: vec![
+                    Pattern::Start,
+                    Pattern::NegativeGroup("abc".to_string()),
+                    Pattern::ZeroOrOne(Box::new(Pattern::Alphanumeric)),
+                    Pattern::OneOrMore(Box::new(Pattern::Character('f'))),
+                    Pattern::Character('o'),
+                    Pattern::Character('o'),
+                    Pattern::OneOrMore(Box::new(Pattern::Digit)),
+                    Pattern::OneOrMore(Box::new(Pattern::PositiveGroup("bar".to_string()))),
+                    Pattern::Group(
+                        1,
+                        None,
+                        Box::new(Pattern::Alternation(vec![
+                            vec![Pattern::Character('c'), Pattern::Character('a')],
+                            vec![Pattern::Character('d')],
+                        ]))
+                    ),
+                    Pattern::End
+                ],
+                group_count: 1,
+                flags: Flags::default(),
+                first_chars: Some(vec![Pattern::NegativeGroup("abc".to_string())]),
+                nfa: regex.nfa().cloned(),
+            }
+        )
+    }
+
+    #[test]
+    fn regex_owns_its_pattern() {
+        fn compile(pattern: &str) -> Regex {
+            let pattern = format!("^{}$", pattern);
+            Regex::parse(&pattern).unwrap()
+        }
+
+        let regex = compile("(?<word>[a-z]+)");
+        let handle = std::thread::spawn(move || regex.is_match("cat"));
+        assert!(handle.join().unwrap());
+
+        fn assert_send_sync<T: Send + Sync + 'static>() {}
+        assert_send_sync::<Regex>();
+    }
+
+    #[test]
+    fn cloned_regex() {
+        let regex = Regex::parse("(?i)^(\\w+) (?<n>\\d+)$").unwrap();
+        let clone = regex.clone();
+        assert_eq!(clone, regex);
+        for input in ["Apple 12", "apple", "12 apple", "APPLE 3"] {
+            assert_eq!(clone.is_match(input), regex.is_match(input));
+            assert_eq!(clone.captures(input), regex.captures(input));
+        }
+        assert_eq!(clone.to_string(), regex.to_string());
+    }
+
+    #[test]
+    fn is_match() {
+        let regex = Regex::parse("\\d apple").unwrap();
+        assert!(regex.is_match("1 apple"));
+        assert!(regex.is_match("sally has 3 apples"));
+        assert!(!regex.is_match("1 orange"));
+        assert!(regex.matches("2 apples").unwrap());
+    }
+
+    #[test]
+    fn is_match_agrees_with_matches() {
+        let cases = [
+            ("\\d apple", "sally has 3 apples"),
+            ("\\d apple", "1 orange"),
+            ("^log", "slog"),
+            ("dog$", "hot dog"),
+            ("ca+t", "caaats"),
+            ("ca?t", "act"),
+            ("[^xyz]", "xyz"),
+            ("[a-c]{2,}", "xbcz"),
+            ("(cat|dog)s?\\b", "dogsled"),
+            ("(a|ab)c", "abc"),
+            ("(\\w+) and \\1", "cat and cat"),
+            ("(\\w+) and \\1", "cat and dog"),
+            ("^.*$", ""),
+            ("\\Bfoo", "a foo"),
+        ];
+        for (pattern, input) in cases {
+            let regex = Regex::parse(pattern).unwrap();
+            let expected = regex.captures(input).is_some();
+            assert_eq!(
+                regex.is_match(input),
+                expected,
+                "{} on {:?}",
+                pattern,
+                input
+            );
+            assert_eq!(regex.matches(input).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn find() {
+        let regex = Regex::parse("\\d+").unwrap();
+        assert_eq!(regex.find("abc123def").map(|m| m.range()), Some(3..6));
+        assert_eq!(regex.find("abcdef"), None);
+
+        let regex = Regex::parse("caf.").unwrap();
+        assert_eq!(regex.find("un café").map(|m| m.range()), Some(3..8));
+
+        let regex = Regex::parse("x*").unwrap();
+        assert_eq!(regex.find("abc").map(|m| m.range()), Some(0..0));
+    }
+
+    #[test]
+    fn match_accessors() {
+        let input = "un café au lait";
+        let found = Regex::parse("caf.").unwrap().find(input).unwrap();
+        assert_eq!(found.start(), 3);
+        assert_eq!(found.end(), 8);
+        assert_eq!(found.range(), 3..8);
+        assert_eq!(found.as_str(), "café");
+
+        let found = Regex::parse("x*").unwrap().find(input).unwrap();
+        assert_eq!(found.as_str(), "");
+        assert_eq!(found.range(), 0..0);
+    }
+
+    #[test]
+    fn captures_iter() {
+        let regex = Regex::parse("(\\w)(\\d)").unwrap();
+        assert_eq!(
+            regex.captures_iter("a1b2").collect::<Vec<_>>(),
+            vec![
+                vec![Some("a1"), Some("a"), Some("1")],
+                vec![Some("b2"), Some("b"), Some("2")],
+            ]
+        );
+        assert_eq!(regex.captures_iter("ab").count(), 0);
+
+        let regex = Regex::parse("(a)?").unwrap();
+        assert_eq!(
+            regex.captures_iter("ba").collect::<Vec<_>>(),
+            vec![
+                vec![Some(""), None],
+                vec![Some("a"), Some("a")],
+                vec![Some(""), None]
+            ]
+        );
+    }
+
+    #[test]
+    fn find_at() {
+        let regex = Regex::parse("\\d+").unwrap();
+        assert_eq!(regex.find_at("xx123", 2).map(|m| m.range()), Some(2..5));
+        assert_eq!(regex.find_at("xx123", 3).map(|m| m.range()), Some(3..5));
+        assert_eq!(regex.find_at("xx123", 0), None);
+        assert_eq!(regex.find_at("xx123", 5), None);
+        assert_eq!(regex.find_at("xx123", 6), None);
+        assert_eq!(regex.find_at("é1", 1), None);
+
+        let regex = Regex::parse("\\bcat").unwrap();
+        assert_eq!(regex.find_at("a cat", 2).map(|m| m.as_str()), Some("cat"));
+        assert_eq!(regex.find_at("bobcat", 3), None);
+        assert_eq!(Regex::parse("^a").unwrap().find_at("aa", 1), None);
+
+        // Tokenize by repeatedly matching where the last token ended
+        let regex = Regex::parse("\\d+|[a-z]+|\\s+").unwrap();
+        let input = "ab 12cd";
+        let mut pos = 0;
+        let mut tokens = Vec::new();
+        while let Some(token) = regex.find_at(input, pos) {
+            tokens.push(token.as_str());
+            pos = token.end();
+        }
+        assert_eq!(tokens, vec!["ab", " ", "12", "cd"]);
+    }
+
+    #[test]
+    fn match_here_returns_end() {
+        let regex = Regex::parse("a+").unwrap();
+        let mut state = State::new(regex.group_count);
+        let patterns = Continuation::new(&regex.patterns);
+        assert_eq!(regex.match_here("aaab", 0, patterns, &mut state), Some(3));
+        assert_eq!(regex.match_here("aaab", 1, patterns, &mut state), Some(3));
+        assert_eq!(regex.match_here("aaab", 3, patterns, &mut state), None);
+
+        let regex = Regex::parse("(a|ab)c").unwrap();
+        let mut state = State::new(regex.group_count);
+        let patterns = Continuation::new(&regex.patterns);
+        assert_eq!(regex.match_here("abcd", 0, patterns, &mut state), Some(3));
+        assert_eq!(
+            regex.match_(" abc", 0, &regex.patterns, &mut state),
+            Some(4)
+        );
+    }
+
+    #[test]
+    fn matches_all_of() {
+        let regex = Regex::parse("^\\d+$").unwrap();
+        assert_eq!(
+            regex.matches_all_of(&["123", "12a", "", "7"]),
+            vec![true, false, false, true]
+        );
+        assert_eq!(regex.matches_all_of(&[]), Vec::<bool>::new());
+
+        let regex = Regex::parse("(\\w)\\1").unwrap();
+        assert_eq!(regex.matches_all_of(&["hello", "world"]), vec![true, false]);
+    }
+
+    #[test]
+    fn matches_with_budget() {
+        let regex = Regex::parse("\\d+").unwrap();
+        assert!(regex.matches_with_budget("abc 123", 100).unwrap());
+        assert!(!regex.matches_with_budget("abc", 100).unwrap());
+
+        // Backreferences turn off memoization, so this backtracks
+        // exponentially
+        let regex = Regex::parse("^(a|aa)+\\1$").unwrap();
+        let input = format!("{}!", "a".repeat(40));
+        let error = regex.matches_with_budget(&input, 10_000).unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<BudgetExceeded>(),
+            Some(&BudgetExceeded { max_steps: 10_000 })
+        );
+        assert_eq!(
+            error.to_string(),
+            "match exceeded its budget of 10000 steps"
+        );
+        assert!(regex.matches_with_budget("aaaa", 10_000).unwrap());
+    }
+
+    #[test]
+    fn depth_exceeded() {
+        // Repetitions are expanded without recursing, so they can go on for
+        // as long as the input does
+        let input = "ab".repeat(100_000);
+        let regex = Regex::parse("(ab)*").unwrap();
+        assert_eq!(regex.find(&input).map(|m| m.range()), Some(0..input.len()));
+        assert!(regex.matches_full(&input));
+        let regex = Regex::parse("^(ab)*\\1$").unwrap();
+        assert!(regex.matches(&input).unwrap());
+        assert!(regex.matches_with_budget(&input, usize::MAX).unwrap());
+        let regex = Regex::parse("a(?:x|y)*?b").unwrap();
+        let input = format!("a{}b", "xy".repeat(50_000));
+        assert_eq!(regex.find(&input).map(|m| m.range()), Some(0..input.len()));
+        let input = format!("{}b", "x".repeat(1_000));
+        let regex = Regex::parse("(?:x)*b").unwrap();
+        assert_eq!(regex.find(&input).map(|m| m.range()), Some(0..input.len()));
+        let regex = Regex::parse("(x|y)+b").unwrap();
+        assert_eq!(regex.find(&input).map(|m| m.range()), Some(0..input.len()));
+        let input = format!("a{}b", "x".repeat(1_000));
+        let regex = Regex::parse("a.*?b").unwrap();
+        assert_eq!(regex.find(&input).map(|m| m.range()), Some(0..input.len()));
+
+        // Each pattern in a sequence still recurses, so the backtracking
+        // matcher gives up on a long enough pattern rather than overflowing
+        // the stack
+        let regex = deep_regex();
+        let error = regex.matches("aa").unwrap_err();
+        assert_eq!(error.downcast_ref::<DepthExceeded>(), Some(&DepthExceeded));
+        assert_eq!(error.to_string(), "match recursed too deeply and gave up");
+        let error = regex.try_find_iter("aa").next().unwrap().unwrap_err();
+        assert_eq!(error.downcast_ref::<DepthExceeded>(), Some(&DepthExceeded));
+        assert!(regex.try_find_iter("aa").nth(1).is_none());
+        let error = regex.matches_with_budget("aa", usize::MAX).unwrap_err();
+        assert_eq!(error.downcast_ref::<DepthExceeded>(), Some(&DepthExceeded));
+    }
+
+    /// A regex too long for the backtracking matcher to match.
+    fn deep_regex() -> Regex {
+        Regex::parse(&format!("(a){}\\1", "a?".repeat(5_000))).unwrap()
+    }
+
+    #[test]
+    #[should_panic(expected = "match recursed too deeply and gave up")]
+    fn depth_exceeded_panics() {
+        deep_regex().find("aa");
+    }
+
+    #[test]
+    fn find_longest() {
+        let regex = Regex::parse("a|ab").unwrap();
+        assert_eq!(regex.find("ab").map(|m| m.range()), Some(0..1));
+        assert_eq!(regex.find_longest("ab").map(|m| m.range()), Some(0..2));
+        assert_eq!(regex.find_longest("xxab").map(|m| m.range()), Some(2..4));
+        assert_eq!(regex.find_longest("b"), None);
+
+        let regex = Regex::parse("(a|ab)(c|bcd)?").unwrap();
+        assert_eq!(regex.find("abcd").map(|m| m.range()), Some(0..4));
+        assert_eq!(regex.find_longest("abcd").map(|m| m.range()), Some(0..4));
+        assert_eq!(regex.find_longest("abc").map(|m| m.range()), Some(0..3));
+
+        let regex = Regex::parse("^(\\w+) \\1|x").unwrap();
+        assert_eq!(regex.find_longest("ab ab").map(|m| m.range()), Some(0..5));
+        assert_eq!(
+            Regex::parse("x*")
+                .unwrap()
+                .find_longest("yx")
+                .map(|m| m.range()),
+            Some(0..0)
+        );
+    }
+
+    #[test]
+    fn shortest_match() {
+        let regex = Regex::parse("a+").unwrap();
+        assert_eq!(regex.shortest_match("aaa"), Some(1));
+        assert_eq!(regex.find("aaa").map(|m| m.range().len()), Some(3));
+        assert_eq!(regex.shortest_match("xxaa"), Some(1));
+        assert_eq!(regex.shortest_match("b"), None);
+
+        let regex = Regex::parse("<.*>").unwrap();
+        assert_eq!(regex.shortest_match("<a><b>"), Some(3));
+        assert_eq!(regex.find("<a><b>").map(|m| m.range().len()), Some(6));
+
+        let regex = Regex::parse("ab|a").unwrap();
+        assert_eq!(regex.shortest_match("ab"), Some(1));
+        assert_eq!(regex.find("ab").map(|m| m.range().len()), Some(2));
+        assert_eq!(Regex::parse("x*").unwrap().shortest_match("xx"), Some(0));
+    }
+
+    #[test]
+    fn match_ends_with_nfa() {
+        // Without backreferences, the NFA finds where matches can end in
+        // time linear in the input, rather than trying each way of matching
+        let input = "a".repeat(100_000);
+        let regex = Regex::parse("a*a*a*a*").unwrap();
+        assert!(regex.nfa().is_some());
+        assert_eq!(
+            regex.find_longest(&input).map(|m| m.range()),
+            Some(0..input.len())
+        );
+        assert_eq!(regex.shortest_match(&input), Some(0));
+        let regex = Regex::parse("(a|aa)+b").unwrap();
+        assert_eq!(regex.find_longest(&input), None);
+
+        // It agrees with the backtracking matcher
+        let cases = [
+            ("a|ab", "xxab"),
+            ("(a|ab)(c|bcd)?", "abcd"),
+            ("<.*>", "x<a><b>"),
+            ("\\bc(at|ow){1,2}s?\\b", "a cowcats cat"),
+            ("(?m)^b+$", "ab\nbb\nb"),
+            ("x*", "yx"),
+            ("a+?b|b", "cab"),
+            ("(a|b)*c", "abababd"),
+        ];
+        for (pattern, input) in cases {
+            let regex = Regex::parse(pattern).unwrap();
+            let backtracking = Regex {
+                nfa: None,
+                ..regex.clone()
+            };
+            assert!(regex.nfa().is_some());
+            assert_eq!(regex.match_ends(input), backtracking.match_ends(input));
+        }
+    }
+
+    #[test]
+    fn find_iter() {
+        let regex = Regex::parse("\\d+").unwrap();
+        assert_eq!(
+            regex
+                .find_iter("a1b22c333")
+                .map(|m| m.range())
+                .collect::<Vec<_>>(),
+            vec![1..2, 3..5, 6..9]
+        );
+        assert_eq!(regex.find_iter("abc").count(), 0);
+
+        let regex = Regex::parse("a*").unwrap();
+        assert_eq!(
+            regex
+                .find_iter("baaé")
+                .map(|m| m.range())
+                .collect::<Vec<_>>(),
+            vec![0..0, 1..3, 3..3, 5..5]
+        );
+
+        let regex = Regex::parse("^\\d").unwrap();
+        assert_eq!(
+            regex.find_iter("12").map(|m| m.range()).collect::<Vec<_>>(),
+            vec![0..1]
+        );
+
+        let regex = Regex::parse("\\bcat").unwrap();
+        assert_eq!(
+            regex
+                .find_iter("cat bobcat cat")
+                .map(|m| m.range())
+                .collect::<Vec<_>>(),
+            vec![0..3, 11..14]
+        );
+    }
+
+    #[test]
+    fn find_overlapping() {
+        let regex = Regex::parse("\\w\\w").unwrap();
+        assert_eq!(
+            regex
+                .find_overlapping("abc")
+                .map(|m| (m.start(), m.end()))
+                .collect::<Vec<_>>(),
+            vec![(0, 2), (1, 3)]
+        );
+        assert_eq!(regex.find_iter("abc").count(), 1);
+
+        let regex = Regex::parse("aa|a").unwrap();
+        assert_eq!(
+            regex
+                .find_overlapping("xaaé")
+                .map(|m| m.range())
+                .collect::<Vec<_>>(),
+            vec![1..3, 2..3]
+        );
+        assert_eq!(
+            Regex::parse("x*")
+                .unwrap()
+                .find_overlapping("é")
+                .map(|m| m.range())
+                .collect::<Vec<_>>(),
+            vec![0..0, 2..2]
+        );
+        assert_eq!(regex.find_overlapping("").count(), 0);
+    }
+
+    #[test]
+    fn replace_all() {
+        let regex = Regex::parse("\\d+").unwrap();
+        assert_eq!(regex.replace_all("a1b2", "#"), "a#b#");
+        assert_eq!(regex.replace_all("abc", "#"), "abc");
+        assert_eq!(regex.replace_all("", "#"), "");
+
+        let regex = Regex::parse("x*").unwrap();
+        assert_eq!(regex.replace_all("ab", "-"), "-a-b-");
+    }
+
+    #[test]
+    fn split() {
+        let regex = Regex::parse("\\d+").unwrap();
+        assert_eq!(regex.split("a1b22c"), vec!["a", "b", "c"]);
+        assert_eq!(regex.split("1a2"), vec!["", "a", ""]);
+        assert_eq!(regex.split("12"), vec!["", ""]);
+        assert_eq!(regex.split("abc"), vec!["abc"]);
+        assert_eq!(regex.split(""), vec![""]);
+
+        let regex = Regex::parse(", *").unwrap();
+        assert_eq!(regex.split("a, b,c"), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn replacen() {
+        let regex = Regex::parse("\\d").unwrap();
+        assert_eq!(regex.replacen("a1b2c3", "#", 1), "a#b2c3");
+        assert_eq!(regex.replacen("a1b2c3", "#", 2), "a#b#c3");
+        assert_eq!(regex.replacen("a1b2c3", "#", 5), "a#b#c#");
+        // A limit of 0 replaces every match
+        assert_eq!(regex.replacen("a1b2c3", "#", 0), "a#b#c#");
+    }
+
+    #[test]
+    fn replace_with_captures() {
+        let regex = Regex::parse("(\\w+)@(\\w+)").unwrap();
+        assert_eq!(regex.replace_all("user@host", "$2.$1"), "host.user");
+        assert_eq!(regex.replace_all("a@b c@d", "[$0]"), "[a@b] [c@d]");
+        assert_eq!(regex.replace_all("a@b", "$3$9$10-"), "-");
+        assert_eq!(regex.replace_all("a@b", "$$1 costs $"), "$1 costs $");
+        assert_eq!(regex.replace_all("a@b", "${1}x"), "ax");
+
+        let regex = Regex::parse("(?<key>\\w+)=(?<value>\\w+)?").unwrap();
+        assert_eq!(regex.replace_all("a=1 b=", "${value}:${key}"), "1:a :b");
+        assert_eq!(regex.replace_all("a=1", "${nope}${key"), "${key");
+    }
+
+    #[test]
+    fn parse_nested_groups() {
+        let regex = Regex::parse("((a|b)|c)").unwrap();
+        assert_eq!(
+            regex,
+            Regex {
+                patterns: vec![Pattern::Group(
+                    1,
+                    None,
+                    Box::new(Pattern::Alternation(vec![
+                        vec![Pattern::Group(
+                            2,
+                            None,
+                            Box::new(Pattern::AnyOf(vec!['a', 'b']))
+                        )],
+                        vec![Pattern::Character('c')],
+                    ]))
+                )],
+                group_count: 2,
+                flags: Flags::default(),
+                first_chars: Some(vec![
+                    Pattern::AnyOf(vec!['a', 'b']),
+                    Pattern::Character('c'),
+                ]),
+                nfa: regex.nfa().cloned(),
+            }
+        );
+
+        assert!(Regex::parse("(a(b|c)").is_err());
+    }
+
+    #[test]
+    fn parse_quantified_group() {
+        let ab = || {
+            Box::new(Pattern::Group(
+                0,
+                None,
+                Box::new(Pattern::Alternation(vec![vec![
+                    Pattern::Character('a'),
+                    Pattern::Character('b'),
+                ]])),
+            ))
+        };
+        assert_eq!(
+            Pattern::parse("(ab)+c"),
+            Ok(("c", Pattern::OneOrMore(ab())))
+        );
+        assert_eq!(Pattern::parse("(ab)?"), Ok(("", Pattern::ZeroOrOne(ab()))));
+        assert_eq!(Pattern::parse("(ab)*"), Ok(("", Pattern::ZeroOrMore(ab()))));
+        assert_eq!(Pattern::parse("(ab)"), Ok(("", *ab())));
+        assert_eq!(
+            Pattern::parse(""),
+            Err(ParseError::MissingPattern { offset: 0 })
+        );
+    }
+
+    #[test]
+    fn empty_alternative() {
+        let regex = Regex::parse("(a|)b").unwrap();
+        assert_eq!(
+            regex.patterns,
+            vec![
+                Pattern::Group(
+                    1,
+                    None,
+                    Box::new(Pattern::Alternation(vec![
+                        vec![Pattern::Character('a')],
+                        vec![],
+                    ]))
+                ),
+                Pattern::Character('b'),
+            ]
+        );
+        assert!(regex.is_match("b"));
+        assert_eq!(regex.find("xb").map(|m| m.range()), Some(1..2));
+        assert_eq!(regex.find("ab").map(|m| m.range()), Some(0..2));
+        assert_eq!(
+            regex.captures("b"),
+            Some(vec![Some("b".to_string()), Some("".to_string())])
+        );
+        assert!(!regex.is_match("a"));
+    }
+
+    #[test]
+    fn nested_groups() {
+        assert!(match_pattern("abd", "^(a(b|c)d)$").unwrap());
+        assert!(match_pattern("acd", "^(a(b|c)d)$").unwrap());
+        assert!(!match_pattern("ad", "^(a(b|c)d)$").unwrap());
+
+        let regex = Regex::parse("((\\w+) (\\w+))").unwrap();
+        assert_eq!(
+            regex.captures("hello world"),
+            Some(vec![
+                Some("hello world".to_string()),
+                Some("hello world".to_string()),
+                Some("hello".to_string()),
+                Some("world".to_string())
+            ])
+        );
+    }
+
+    #[test]
+    fn case_insensitive() {
+        let flags = Flags {
+            case_insensitive: true,
+            ..Default::default()
+        };
+        let regex = Regex::parse_with_flags("hello", flags).unwrap();
+        assert!(regex.is_match("HELLO"));
+        assert!(regex.is_match("say HeLLo"));
+        assert!(!Regex::parse("hello").unwrap().is_match("HELLO"));
+
+        let regex = Regex::parse_with_flags("^[a-z]+$", flags).unwrap();
+        assert!(regex.is_match("MiXeD"));
+        let regex = Regex::parse_with_flags("[^a]", flags).unwrap();
+        assert!(!regex.is_match("A"));
+        let regex = Regex::parse_with_flags("(cat) \\1", flags).unwrap();
+        assert!(regex.is_match("Cat CAT"));
+    }
+
+    #[test]
+    fn unicode_case_insensitive() {
+        let regex = Regex::parse("(?i)привет").unwrap();
+        assert!(!regex.is_match("ПРИВЕТ"));
+        let regex = Regex::parse("(?iu)привет").unwrap();
+        assert!(regex.is_match("ПРИВЕТ"));
+        assert!(regex.is_match("ПрИвЕт мир"));
+
+        let regex = RegexBuilder::new()
+            .case_insensitive(true)
+            .unicode(true)
+            .build("^σοφος$")
+            .unwrap();
+        assert!(regex.is_match("ΣΟΦΟΣ"));
+        assert!(regex.is_match("σοφοσ"));
+        assert!(!regex.is_match("σοφοι"));
+
+        let regex = Regex::parse("(?iu)[α-ω]+").unwrap();
+        assert_eq!(regex.find("ok ΑΒΓ").map(|m| m.as_str()), Some("ΑΒΓ"));
+        assert!(!Regex::parse("(?iu)[^σ]").unwrap().is_match("Σς"));
+        assert!(Regex::parse("(?iu)CAT").unwrap().is_match("cat"));
+
+        let regex = Regex::parse("(?iu)(σ)\\1").unwrap();
+        assert!(regex.is_match("σΣ"));
+        assert!(!regex.is_match("σα"));
+        // The Kelvin sign folds to `k` but is longer in UTF-8
+        let regex = Regex::parse("(?iu)^(k)\\1!$").unwrap();
+        assert!(regex.is_match("k\u{212a}!"));
+        assert!(!Regex::parse("(?i)(σ)\\1").unwrap().is_match("σΣ"));
+    }
+
+    #[test]
+    fn inline_case_insensitive() {
+        let regex = Regex::parse("(?i)cat").unwrap();
+        assert!(regex.is_match("CAT"));
+        assert!(regex.is_match("a Cat"));
+        assert!(!regex.is_match("dog"));
+
+        assert!(Regex::parse("cat(?i)").is_err());
+        assert!(Regex::parse("(a(?i)b)").is_err());
+    }
+
+    #[test]
+    fn multiline() {
+        let input = "dog\ncat\n";
+        assert!(!match_pattern(input, "^cat").unwrap());
+        assert!(!match_pattern(input, "dog$").unwrap());
+        assert!(match_pattern(input, "(?m)^cat").unwrap());
+        assert!(match_pattern(input, "(?m)dog$").unwrap());
+        assert!(match_pattern(input, "(?m)^$").unwrap());
+        assert!(!match_pattern(input, "(?m)\\Acat").unwrap());
+        assert!(!match_pattern(input, "(?m)dog\\z").unwrap());
+
+        let flags = Flags {
+            multiline: true,
+            ..Default::default()
+        };
+        let regex = Regex::parse_with_flags("^\\w+$", flags).unwrap();
+        assert_eq!(
+            regex
+                .find_iter(input)
+                .map(|m| m.range())
+                .collect::<Vec<_>>(),
+            vec![0..3, 4..7]
+        );
+        assert!(Regex::parse_with_flags("^(c)at", flags)
+            .unwrap()
+            .captures(input)
+            .is_some());
+
+        let regex = Regex::parse("(?mi)^CAT").unwrap();
+        assert!(regex.is_match(input));
+        assert_eq!(regex.to_string(), "(?im)^CAT");
+        assert_eq!(
+            Regex::parse("(?mx)a"),
+            Err(ParseError::UnknownFlag {
+                offset: 3,
+                flag: 'x'
+            })
+        );
+        assert_eq!(
+            Regex::parse("(?i"),
+            Err(ParseError::UnterminatedFlags { offset: 0 })
+        );
+        assert_eq!(
+            Regex::parse("(?)a"),
+            Err(ParseError::UnknownFlag {
+                offset: 2,
+                flag: ')'
+            })
+        );
+        assert_eq!(
+            Regex::parse("(?i)(?)a"),
+            Err(ParseError::UnknownFlag {
+                offset: 6,
+                flag: ')'
+            })
+        );
+
+        let regex = Regex::parse("(?i)(?m)^cat").unwrap();
+        assert_eq!(regex, Regex::parse("(?im)^cat").unwrap());
+        assert!(regex.is_match("dog\nCAT"));
+        assert_eq!(regex.to_string(), "(?im)^cat");
+        assert_eq!(
+            Regex::parse("(?i)a(?m)"),
+            Err(ParseError::MisplacedFlags { offset: 5 })
+        );
+        assert_eq!(
+            Regex::parse("(?im").unwrap_err().to_string(),
+            "unterminated inline flags starting at offset 0"
+        );
+    }
+
+    #[test]
+    fn regex_builder() {
+        let regex = RegexBuilder::new()
+            .case_insensitive(true)
+            .multi_line(true)
+            .build("^cat$")
+            .unwrap();
+        assert!(regex.is_match("dog\nCAT"));
+        assert_eq!(regex.to_string(), "(?im)^cat$");
+
+        let mut builder = RegexBuilder::new();
+        builder.dot_matches_newline(true);
+        assert!(builder.build("a.b").unwrap().is_match("a\nb"));
+        assert!(!builder
+            .dot_matches_newline(false)
+            .build("a.b")
+            .unwrap()
+            .is_match("a\nb"));
+        assert_eq!(
+            RegexBuilder::new().build("a").unwrap(),
+            Regex::parse("a").unwrap()
+        );
+    }
+
+    #[test]
+    fn display_round_trip() {
+        for pattern in [
+            "^[^abc]\\w?f+oo\\d+[bar]+(ca|d)$",
+            "((a|b)|c)",
+            "(?i)\\bcat\\B",
+            "a{2}b{1,3}c{0,}",
+            "\\D\\W\\S\\s\\n\\t\\r.",
+            "(\\w+) \\1",
+            "1\\+2\\.\\$\\(x\\)\\|\\{\\\\",
+            "[a-z-]+",
+            "cat|dog",
+            "a|b",
+            "x(?:cat|dog)+",
+            "(?:a|b)c",
+            "(?:ab){2}",
+            "(a|b)",
+            "(?:a)",
+            "(?:ab)",
+            "(?:)",
+            "(?:(?:a))",
+            "(?i)(?:a)",
+        ] {
+            let regex = Regex::parse(pattern).unwrap();
+            assert_eq!(regex.to_string(), pattern);
+            assert_eq!(Regex::parse(&regex.to_string()).unwrap(), regex);
+        }
+
+        let regex = Regex::parse("a\\/b{2,}").unwrap();
+        assert_eq!(regex.to_string(), "a/b{2,}");
+    }
+
+    #[test]
+    fn pathological_patterns() {
+        let input = "a".repeat(30);
+        let pattern = format!("^{}{}$", "a?".repeat(30), "a".repeat(30));
+        assert!(match_pattern(&input, &pattern).unwrap());
+        assert!(!match_pattern(&input, "(a+)+b").unwrap());
+        assert!(!match_pattern(&input, "(a|aa)*c").unwrap());
+        assert!(!match_pattern(&input, "(a*)*b").unwrap());
+    }
+
+    #[test]
+    fn memoized_backtracking() {
+        let input = "a".repeat(200);
+        let regex = Regex::parse("a*a*a*a*b").unwrap();
+        assert_eq!(regex.find(&input), None);
+        assert_eq!(
+            regex.find(&format!("{}b", input)).map(|m| m.range()),
+            Some(0..201)
+        );
+    }
+
+    #[test]
+    fn empty_repetitions() {
+        let regex = Regex::parse("(a*)*b").unwrap();
+        assert_eq!(regex.find("aaa"), None);
+        assert_eq!(regex.find("aab").map(|m| m.range()), Some(0..3));
+
+        let regex = Regex::parse("(a|)+").unwrap();
+        assert_eq!(regex.find("aa").map(|m| m.range()), Some(0..2));
+        assert_eq!(regex.find("b").map(|m| m.range()), Some(0..0));
+
+        let regex = Regex::parse("(x?){2,}y").unwrap();
+        assert_eq!(regex.find("xy").map(|m| m.range()), Some(0..2));
+        assert_eq!(regex.find("z"), None);
+
+        let regex = Regex::parse("(a*)*?b").unwrap();
+        assert_eq!(regex.find("aaa"), None);
+    }
+
+    #[test]
+    fn matches_bytes() {
+        let regex = Regex::parse("\\d+").unwrap();
+        assert!(regex.matches_bytes(b"abc123"));
+        assert!(!regex.matches_bytes(b"abc"));
+        assert!(regex.matches_bytes(b"\xff\xfe1"));
+
+        let regex = Regex::parse("(?m)^b\\b").unwrap();
+        assert!(regex.matches_bytes(b"a\nb c"));
+        assert!(!regex.matches_bytes(b"a\nbc"));
+        assert!(Regex::parse("^(a)\\1$").unwrap().matches_bytes(b"aa"));
+        assert!(Regex::parse("^.$").unwrap().matches_bytes(&[0xe9]));
+    }
+
+    #[test]
+    fn first_chars() {
+        let first_chars = |pattern| Regex::parse(pattern).unwrap().first_chars;
+        assert_eq!(first_chars("dog"), Some(vec![Pattern::Character('d')]));
+        assert_eq!(
+            first_chars("\\b(a?|b)*c+"),
+            Some(vec![
+                Pattern::Character('a'),
+                Pattern::Character('b'),
+                Pattern::Character('c'),
+            ])
+        );
+        assert_eq!(first_chars("a*"), None);
+        assert_eq!(first_chars("(a)\\1"), Some(vec![Pattern::Character('a')]));
+        assert_eq!(first_chars("(a?)\\1b"), None);
+
+        let regex = Regex::parse("(?i)dog").unwrap();
+        assert_eq!(regex.find("hot DOG").map(|m| m.range()), Some(4..7));
+        let regex = Regex::parse("x|y?$").unwrap();
+        assert_eq!(regex.find("ab").map(|m| m.range()), Some(2..2));
+    }
+
+    #[test]
+    fn nfa() {
+        let regex = Regex::parse("\\bc(at|ow){1,2}s?\\b").unwrap();
+        let nfa = regex.nfa().unwrap();
+        assert!(nfa.is_match("a cat"));
+        assert!(nfa.is_match("cowats"));
+        assert!(!nfa.is_match("cowcat"));
+        assert!(!nfa.is_match("scat"));
+
+        // The NFA owns its patterns, so it can outlive the regex
+        let nfa = Regex::parse("(?i)^ab+$").unwrap().nfa().unwrap().clone();
+        assert!(nfa.is_match("ABb"));
+        assert!(!nfa.is_match("ABc"));
+
+        // The NFA is compiled along with the regex, and recompiled by methods
+        // that change its patterns
+        let regex = Regex::parse("cat").unwrap().whole_line();
+        assert!(!regex.nfa().unwrap().is_match("cats"));
+        assert_eq!(
+            regex.matches_all_of(&["cat", "cats", "a\ncat"]),
+            vec![true, false, false]
+        );
+        assert!(!regex.is_match("cats"));
+        assert!(regex.is_match("cat"));
+
+        assert!(Regex::parse("(a)\\1").unwrap().nfa().is_none());
+    }
+
+    #[test]
+    fn trailing_backslash() {
+        assert_eq!(
+            Regex::parse("a\\").unwrap_err().to_string(),
+            "trailing backslash at offset 1"
+        );
+        assert_eq!(
+            Regex::parse("\\"),
+            Err(ParseError::TrailingBackslash { offset: 0 })
+        );
+        assert_eq!(
+            Regex::parse("(a\\"),
+            Err(ParseError::TrailingBackslash { offset: 2 })
+        );
+        assert!(match_pattern("a\\", "a\\\\").unwrap());
+    }
+
+    #[test]
+    fn nothing_to_repeat() {
+        assert_eq!(
+            Regex::parse("+a"),
+            Err(ParseError::NothingToRepeat { offset: 0 })
+        );
+        assert_eq!(
+            Regex::parse("a+++"),
+            Err(ParseError::NothingToRepeat { offset: 3 })
+        );
+        assert_eq!(
+            Regex::parse("*x").unwrap_err().to_string(),
+            "nothing to repeat at offset 0"
+        );
+        assert_eq!(
+            Regex::parse("a{2}??"),
+            Err(ParseError::NothingToRepeat { offset: 5 })
+        );
+        assert_eq!(
+            Regex::parse("(|?)"),
+            Err(ParseError::NothingToRepeat { offset: 2 })
+        );
+        assert!(match_pattern("+a", "\\+a").unwrap());
+        assert!(match_pattern("a+", "[+]").unwrap());
+
+        // A backreference can be repeated like any other pattern
+        let regex = Regex::parse("(a)\\1+").unwrap();
+        assert_eq!(regex.find("aaa").map(|m| m.range()), Some(0..3));
+        assert!(!regex.is_match("a"));
+        assert!(match_pattern("b", "^(a)?\\1*b").unwrap());
+        assert_eq!(Regex::parse("(a)\\1{2}").unwrap().to_string(), "(a)\\1{2}");
+    }
+
+    #[test]
+    fn anchored() {
+        let regex = Regex::parse("^abc$").unwrap();
+        assert!(regex.is_anchored_start());
+        assert!(regex.is_anchored_end());
+
+        let regex = Regex::parse("\\Aabc").unwrap();
+        assert!(regex.is_anchored_start());
+        assert!(!regex.is_anchored_end());
+
+        let regex = Regex::parse("abc\\z").unwrap();
+        assert!(!regex.is_anchored_start());
+        assert!(regex.is_anchored_end());
+
+        let regex = Regex::parse("a^b|c$d").unwrap();
+        assert!(!regex.is_anchored_start());
+        assert!(!regex.is_anchored_end());
+        assert!(!Regex::parse("").unwrap().is_anchored_start());
+        assert!(Regex::literal("abc").whole_line().is_anchored_end());
+    }
+
+    #[test]
+    fn possessive_quantifiers() {
+        assert!(match_pattern("aaa", "a+a").unwrap());
+        assert!(!match_pattern("aaa", "a++a").unwrap());
+        assert!(!match_pattern("aaa", "a*+a").unwrap());
+        assert!(match_pattern("ab", "a?+b").unwrap());
+        assert!(match_pattern("aaab", "^a{2,}+b$").unwrap());
+        assert!(!match_pattern("abab", "(ab)*+ab").unwrap());
+
+        let regex = Regex::parse("\\d++x").unwrap();
+        assert_eq!(regex.find("12 345x").map(|m| m.range()), Some(3..7));
+        assert_eq!(regex.to_string(), "\\d++x");
+        assert!(regex.nfa().is_none());
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(
+            Regex::parse("ab[cd"),
+            Err(ParseError::UnterminatedGroup { offset: 2 })
+        );
+        assert_eq!(
+            Regex::parse("a(b|c"),
+            Err(ParseError::UnterminatedAlternation { offset: 1 })
+        );
+        assert_eq!(
+            Regex::parse("(a|b[)"),
+            Err(ParseError::UnterminatedGroup { offset: 4 })
+        );
+        assert_eq!(
+            Regex::parse("ab\\"),
+            Err(ParseError::TrailingBackslash { offset: 2 })
+        );
+        assert_eq!(
+            Regex::parse("a\\x4"),
+            Err(ParseError::InvalidEscape { offset: 1 })
+        );
+        assert_eq!(
+            Regex::parse("\\xg1"),
+            Err(ParseError::InvalidEscape { offset: 0 })
+        );
+        assert_eq!(
+            Regex::parse("\\u{D800}"),
+            Err(ParseError::InvalidEscape { offset: 0 })
+        );
+        assert_eq!(
+            Regex::parse("a\\u263A"),
+            Err(ParseError::InvalidEscape { offset: 1 })
+        );
+        assert_eq!(
+            Regex::parse("\\u{}"),
+            Err(ParseError::InvalidEscape { offset: 0 })
+        );
+        assert_eq!(
+            Regex::parse("(?i)a{2"),
+            Err(ParseError::InvalidRepetition { offset: 5 })
+        );
+        assert_eq!(
+            Regex::parse("a(?i)"),
+            Err(ParseError::MisplacedFlags { offset: 1 })
+        );
+        assert_eq!(
+            Regex::parse("é[ü"),
+            Err(ParseError::UnterminatedGroup { offset: 2 })
+        );
+        assert_eq!(
+            Regex::parse("ab)"),
+            Err(ParseError::UnmatchedParen { offset: 2 })
+        );
+        assert_eq!(
+            Regex::parse("(a)b)|c"),
+            Err(ParseError::UnmatchedParen { offset: 4 })
+        );
+        assert!(Regex::parse("ab\\)").unwrap().is_match("ab)"));
+        assert!(Regex::parse("a[)]").unwrap().is_match("a)"));
+        assert_eq!(
+            Regex::parse("ab[cd").unwrap_err().to_string(),
+            "unterminated character class starting at offset 2"
+        );
+    }
+
+    #[test]
+    fn escaped_literals() {
+        assert!(match_pattern("a/b", "a\\/b").unwrap());
+        assert!(match_pattern("a-b", "^a\\-b$").unwrap());
+        assert!(!match_pattern("ab", "a\\-b").unwrap());
+        assert!(match_pattern("1+++2", "^1\\++2$").unwrap());
+        assert!(match_pattern("a\\b", "a\\\\b").unwrap());
+        assert!(Regex::parse("a\\").is_err());
+    }
+
+    #[test]
+    fn escaped_delimiters() {
+        assert!(match_pattern("a(b", "a\\(b").unwrap());
+        assert!(match_pattern("f(x) = y", "^f\\(\\w\\) = y$").unwrap());
+        assert!(match_pattern("a|b", "^a\\|b$").unwrap());
+        assert!(!match_pattern("a", "^a\\|b$").unwrap());
+        assert!(match_pattern("[1]", "\\[\\d\\]").unwrap());
+        assert!(match_pattern("{2}", "^\\{2\\}$").unwrap());
+        assert!(match_pattern("a{2}", "a\\{2}").unwrap());
+        assert!(!match_pattern("aa", "a\\{2}").unwrap());
+    }
+
+    #[test]
+    fn unicode_patterns() {
+        assert!(match_pattern("café", "^caf[éè]$").unwrap());
+        assert!(match_pattern("naïve", "ï+v").unwrap());
+        assert!(match_pattern("日本語", "(本|中)語").unwrap());
+        assert!(!match_pattern("cafe", "café").unwrap());
+        assert_eq!(
+            Regex::parse("ü{2}x")
+                .unwrap()
+                .find("aüüx")
+                .map(|m| m.range()),
+            Some(1..6)
+        );
+    }
+
+    #[test]
+    fn top_level_alternation() {
+        assert!(match_pattern("cat", "cat|dog").unwrap());
+        assert!(match_pattern("dog", "cat|dog").unwrap());
+        assert!(!match_pattern("cow", "cat|dog").unwrap());
+        assert!(match_pattern("a cat", "^cat|cat$").unwrap());
+        assert!(!match_pattern("a cats", "^cat|cat$").unwrap());
+
+        let regex = Regex::parse("^cat|dogs?|(c|h)ow$").unwrap();
+        assert_eq!(regex.group_count, 1);
+        assert_eq!(regex.find("hotdogs").map(|m| m.range()), Some(3..7));
+        assert_eq!(regex.find("catdog").map(|m| m.range()), Some(0..3));
+        assert_eq!(regex.find("a cat"), None);
+        assert_eq!(regex.captures("a how").unwrap()[1].as_deref(), Some("h"));
+        assert_eq!(regex.to_string(), "^cat|dogs?|(c|h)ow$");
+    }
+
+    #[test]
+    fn multi_char_patterns_never_match_a_char() {
+        let pattern = Pattern::OneOrMore(Box::new(Pattern::Alternation(vec![vec![
+            Pattern::Character('a'),
+        ]])));
+        assert!(!pattern.matches('a'));
+        assert!(!Pattern::Start.matches('a'));
+        assert!(!Pattern::Backreference(1).matches('a'));
+        assert!(Pattern::Character('a').matches('a'));
+    }
+
+    #[test]
+    fn single_character() {
+        assert!(match_pattern("apple", "a").unwrap());
+        assert!(!match_pattern("dog", "a").unwrap());
+    }
+
+    #[test]
+    fn digit_character_class() {
+        assert!(match_pattern("3", "\\d").unwrap());
+        assert!(!match_pattern("c", "\\d").unwrap());
+    }
+
+    #[test]
+    fn alphanumeric_character_class() {
+        assert!(match_pattern("foo101", "\\w").unwrap());
+        assert!(!match_pattern("$!?", "\\w").unwrap());
+    }
+
+    #[test]
+    fn negated_character_classes() {
+        assert!(match_pattern("x", "\\D").unwrap());
+        assert!(!match_pattern("7", "\\D").unwrap());
+        assert!(match_pattern("!", "\\W").unwrap());
+        assert!(!match_pattern("a", "\\W").unwrap());
+        assert!(match_pattern("12ab34", "^\\d+\\D+\\d+$").unwrap());
+        assert!(match_pattern("foo, bar", "foo\\W*bar").unwrap());
+        assert!(match_pattern("foobar", "foo\\W?bar").unwrap());
+        assert!(match_pattern("a--b", "a\\W{2}b").unwrap());
+    }
+
+    #[test]
+    fn whitespace_character_class() {
+        assert!(match_pattern("1 a", "\\d\\s\\w").unwrap());
+        assert!(match_pattern("a\tb", "a\\sb").unwrap());
+        assert!(!match_pattern("ab", "a\\sb").unwrap());
+        assert!(match_pattern("a   b", "a\\s+b").unwrap());
+        assert!(match_pattern("ab", "a\\s?b").unwrap());
+        assert!(match_pattern("a b", "\\S").unwrap());
+        assert!(!match_pattern(" \t ", "\\S").unwrap());
+    }
+
+    #[test]
+    fn positive_character_group() {
+        assert!(match_pattern("apple", "[abc]").unwrap());
+        assert!(!match_pattern("dog", "[abc]").unwrap());
+    }
+
+    #[test]
+    fn negative_character_group() {
+        assert!(match_pattern("dog", "[^abc]").unwrap());
+        assert!(!match_pattern("cab", "[^abc]").unwrap());
+    }
+
+    #[test]
+    fn combined_classes() {
+        assert!(match_pattern("1 apple", "\\d apple").unwrap());
+        assert!(!match_pattern("1 orange", "\\d apple").unwrap());
+
+        assert!(match_pattern("100 apples", "\\d\\d\\d apple").unwrap());
+        assert!(!match_pattern("1 apple", "\\d\\d\\d apple").unwrap());
+
+        assert!(match_pattern("3 dogs", "\\d \\w\\w\\ws").unwrap());
+        assert!(match_pattern("4 cats", "\\d \\w\\w\\ws").unwrap());
+        assert!(!match_pattern("1 dog", "\\d \\w\\w\\ws").unwrap());
+
+        assert!(!match_pattern("sally has 12 apples", "\\d\\\\d\\\\d apples").unwrap());
+    }
+
+    #[test]
+    fn character_group_escapes() {
+        assert!(match_pattern("5", "^[\\d.]$").unwrap());
+        assert!(match_pattern(".", "^[\\d.]$").unwrap());
+        assert!(!match_pattern("a", "[\\d.]").unwrap());
+        assert!(match_pattern("]", "[\\]]").unwrap());
+        assert!(match_pattern("a\\b", "^a[\\\\]b$").unwrap());
+        assert!(match_pattern("x y", "x[\\s]y").unwrap());
+        assert!(!match_pattern(" ", "[^\\s]").unwrap());
+        assert!(match_pattern("a1-", "^[\\w-]+$").unwrap());
+        assert!(match_pattern("-", "^[a\\-z]$").unwrap());
+        assert!(!match_pattern("b", "[a\\-z]").unwrap());
+        assert!(Regex::parse("[a\\]").is_err());
+
+        assert!(match_pattern("A", "^[\\x41]$").unwrap());
+        assert!(!match_pattern("x", "[\\x41]").unwrap());
+        assert!(!match_pattern("1", "[\\x41]").unwrap());
+        assert!(match_pattern("Q", "^[\\x41-\\x5A]$").unwrap());
+        assert!(!match_pattern("q", "[\\x41-\\x5A]").unwrap());
+        assert!(match_pattern("]", "^[\\x5D]$").unwrap());
+        assert!(match_pattern("a\tb", "a[\\t]b").unwrap());
+        assert_eq!(
+            Regex::parse("a[\\x4]"),
+            Err(ParseError::InvalidEscape { offset: 2 })
+        );
+    }
+
+    #[test]
+    fn posix_classes() {
+        assert!(match_pattern("2024", "^[[:digit:]]+$").unwrap());
+        assert!(!match_pattern("7", "[[:alpha:]]").unwrap());
+        assert!(match_pattern("a 1", "^[[:alpha:][:space:]]+1$").unwrap());
+        assert!(match_pattern("Ab", "^[[:upper:]][[:lower:]]$").unwrap());
+        assert!(!match_pattern("ab", "^[[:upper:]]").unwrap());
+        assert!(match_pattern("x_9", "^[^[:punct:]]_[[:alnum:]]$").unwrap());
+        assert!(match_pattern("[", "^[[]$").unwrap());
+        assert_eq!(
+            Regex::parse("a[[:digits:]]"),
+            Err(ParseError::UnknownClass {
+                offset: 2,
+                name: "digits".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn caret_in_character_group() {
+        assert!(match_pattern("^", "[a^]").unwrap());
+        assert!(match_pattern("^", "[a^b]").unwrap());
+        assert!(!match_pattern("c", "[a^b]").unwrap());
+        assert!(!match_pattern("^", "[^^]").unwrap());
+        assert!(match_pattern("a", "[^^]").unwrap());
+        assert!(match_pattern("^", "^[\\^]$").unwrap());
+        assert!(!match_pattern("a", "[\\^]").unwrap());
+    }
+
+    #[test]
+    fn leading_bracket_in_character_group() {
+        assert!(match_pattern("]", "[]]").unwrap());
+        assert!(!match_pattern("a", "[]]").unwrap());
+        assert!(match_pattern("b", "[]ab]").unwrap());
+        assert!(match_pattern("a", "[^]]").unwrap());
+        assert!(!match_pattern("]", "[^]]").unwrap());
+        assert!(match_pattern("x]", "x[]]$").unwrap());
+        assert_eq!(Regex::parse("[]]").unwrap().to_string(), "[]]");
+        assert_eq!(
+            Regex::parse("a[^]"),
+            Err(ParseError::UnterminatedGroup { offset: 1 })
+        );
+        assert_eq!(
+            Regex::parse("[]"),
+            Err(ParseError::UnterminatedGroup { offset: 0 })
+        );
+        assert_eq!(
+            Regex::parse("[abc").unwrap_err().to_string(),
+            "unterminated character class starting at offset 0"
+        );
+    }
+
+    #[test]
+    fn character_ranges() {
+        assert!(match_pattern("c", "[a-f]").unwrap());
+        assert!(!match_pattern("g", "[a-f]").unwrap());
+        assert!(match_pattern("Q", "[A-Z]").unwrap());
+        assert!(match_pattern("7", "[0-9]").unwrap());
+        assert!(match_pattern("x7", "[a-z][0-9]").unwrap());
+        assert!(match_pattern("5", "[^a-z]").unwrap());
+        assert!(!match_pattern("q", "[^a-z]").unwrap());
+        assert!(!match_pattern("-", "[a-z]").unwrap());
+        assert!(match_pattern("-", "[-abc]").unwrap());
+        assert!(match_pattern("-", "[abc-]").unwrap());
+        assert!(!match_pattern("b", "[a-]").unwrap());
+
+        assert_eq!(
+            Regex::parse("x[z-a]"),
+            Err(ParseError::InvalidRange { offset: 2 })
+        );
+        assert_eq!(
+            Regex::parse("[^ab-\\]]"),
+            Err(ParseError::InvalidRange { offset: 3 })
+        );
+        assert_eq!(
+            Regex::parse("[z-a]").unwrap_err().to_string(),
+            "character range out of order at offset 1"
+        );
+        assert!(match_pattern("a", "[a-a]").unwrap());
+    }
+
+    #[test]
+    fn whitespace_escapes() {
+        assert!(match_pattern("a\tb", "a\\tb").unwrap());
+        assert!(!match_pattern("a b", "a\\tb").unwrap());
+        assert!(match_pattern("dog\n", "dog\\n$").unwrap());
+        assert!(match_pattern("line\r\n", "\\r\\n").unwrap());
+        assert!(match_pattern("a\t\tb", "^a\\t+b$").unwrap());
+    }
+
+    #[test]
+    fn start_anchor() {
+        assert!(match_pattern("log", "^log").unwrap());
+        assert!(!match_pattern("slog", "^log").unwrap());
+    }
+
+    #[test]
+    fn end_anchor() {
+        assert!(match_pattern("dog", "dog$").unwrap());
+        assert!(!match_pattern("dogs", "dog$").unwrap());
+    }
+
+    #[test]
+    fn start_anchor_mid_pattern() {
+        assert!(!match_pattern("ab", "a^b").unwrap());
+        assert!(!match_pattern("a^b", "a^b").unwrap());
+        assert!(match_pattern("b", "\\b^b").unwrap());
+        assert!(match_pattern("a", "^^a").unwrap());
+        assert!(match_pattern("cb", "(a|^c)b").unwrap());
+        assert!(!match_pattern("xcb", "(a|^c)b").unwrap());
+        assert_eq!(Regex::parse("a^b").unwrap().captures("ab"), None);
+    }
+
+    #[test]
+    fn end_anchor_mid_pattern() {
+        assert!(!match_pattern("ab", "a$b").unwrap());
+        assert!(!match_pattern("a$b", "a$b").unwrap());
+        assert!(match_pattern("a", "a$\\b").unwrap());
+        assert!(match_pattern("a", "a$$").unwrap());
+    }
+
+    #[test]
+    fn string_anchors() {
+        assert!(match_pattern("dog", "\\Adog\\z").unwrap());
+        assert!(!match_pattern("dog\n", "\\Adog\\z").unwrap());
+        assert!(!match_pattern("hotdog", "\\Adog").unwrap());
+        assert!(match_pattern("hotdog", "dog\\z").unwrap());
+        assert!(match_pattern("hot", "hot|\\Adog").unwrap());
+
+        let regex = Regex::parse("\\A\\w+|\\d\\z").unwrap();
+        assert_eq!(regex.find("ab 12").map(|m| m.range()), Some(0..2));
+        assert_eq!(regex.find(" ab 12").map(|m| m.range()), Some(5..6));
+        assert_eq!(regex.to_string(), "\\A\\w+|\\d\\z");
+    }
+
+    #[test]
+    fn word_boundary() {
+        assert!(match_pattern("the cat sat", "\\bcat\\b").unwrap());
+        assert!(match_pattern("cat", "\\bcat\\b").unwrap());
+        assert!(!match_pattern("category", "\\bcat\\b").unwrap());
+        assert!(!match_pattern("bobcat", "\\bcat").unwrap());
+        assert!(match_pattern("snake_case", "e\\b").unwrap());
+        assert!(!match_pattern("snake_case", "e\\b_").unwrap());
+    }
+
+    #[test]
+    fn non_word_boundary() {
+        assert!(match_pattern("category", "\\Bat").unwrap());
+        assert!(!match_pattern("at home", "\\Bat").unwrap());
+        assert!(match_pattern("bobcat", "\\Bcat\\b").unwrap());
+    }
+
+    #[test]
+    fn one_or_more() {
+        assert!(match_pattern("apple", "a+").unwrap());
+        assert!(match_pattern("SaaS", "a+").unwrap());
+        assert!(!match_pattern("dog", "a+").unwrap());
+    }
+
+    #[test]
+    fn greedy_quantifiers() {
+        assert!(match_pattern("aaab", "a+ab").unwrap());
+        assert!(match_pattern("aaa", "^a+a$").unwrap());
+        assert!(match_pattern("aaa", "^a*a$").unwrap());
+        assert!(!match_pattern("a", "^a+a$").unwrap());
+        assert_eq!(
+            Regex::parse("a+").unwrap().find("baaab").map(|m| m.range()),
+            Some(1..4)
+        );
+        assert_eq!(
+            Regex::parse("a*").unwrap().find("aaab").map(|m| m.range()),
+            Some(0..3)
+        );
+        assert_eq!(
+            Regex::parse("ab?").unwrap().find("abc").map(|m| m.range()),
+            Some(0..2)
+        );
+    }
+
+    #[test]
+    fn lazy_quantifiers() {
+        let regex = Regex::parse("a+?").unwrap();
+        assert_eq!(regex.find("aaa").map(|m| m.range()), Some(0..1));
+        let regex = Regex::parse("a+").unwrap();
+        assert_eq!(regex.find("aaa").map(|m| m.range()), Some(0..3));
+
+        let regex = Regex::parse("<.*?>").unwrap();
+        assert_eq!(regex.find("<a><b>").map(|m| m.as_str()), Some("<a>"));
+        let regex = Regex::parse("a??b").unwrap();
+        assert_eq!(regex.find("aab").map(|m| m.range()), Some(1..3));
+        let regex = Regex::parse("(ab){1,3}?").unwrap();
+        assert_eq!(regex.find("ababab").map(|m| m.range()), Some(0..2));
+        let regex = Regex::parse("^(a*?)(a*)$").unwrap();
+        assert_eq!(
+            regex.captures("aaa"),
+            Some(vec![
+                Some("aaa".to_string()),
+                Some("".to_string()),
+                Some("aaa".to_string())
+            ])
+        );
+
+        // Laziness doesn't change whether a line matches
+        let regex = Regex::parse("^\\d+?x$").unwrap();
+        assert!(regex.is_match("123x"));
+        assert!(!regex.is_match("123"));
+        assert_eq!(regex.to_string(), "^\\d+?x$");
+    }
+
+    #[test]
+    fn zero_or_one() {
+        assert!(match_pattern("dogs", "dogs?").unwrap());
+        assert!(match_pattern("dog", "dogs?").unwrap());
+        assert!(!match_pattern("cat", "dogs?").unwrap());
+        assert!(!match_pattern("cag", "ca?t").unwrap());
+    }
+
+    #[test]
+    fn zero_or_more() {
+        assert!(match_pattern("color", "colou*r").unwrap());
+        assert!(match_pattern("colour", "colou*r").unwrap());
+        assert!(match_pattern("colouuur", "colou*r").unwrap());
+        assert!(!match_pattern("colr", "colou*r").unwrap());
+        assert!(match_pattern("123", "\\d*").unwrap());
+        assert!(match_pattern("cab", "^[abc]*$").unwrap());
+        assert!(match_pattern("", "a*").unwrap());
+    }
+
+    #[test]
+    fn bounded_repetition() {
+        assert!(match_pattern("123", "^\\d{3}$").unwrap());
+        assert!(!match_pattern("12", "^\\d{3}$").unwrap());
+        assert!(!match_pattern("1234", "^\\d{3}$").unwrap());
+        assert!(match_pattern("aaaaa", "a{2,4}").unwrap());
+        assert!(!match_pattern("aaaaa", "^a{2,4}$").unwrap());
+        assert!(match_pattern("aaaa", "^a{2,4}$").unwrap());
+        assert!(match_pattern("b", "^a{0,3}b").unwrap());
+        assert!(match_pattern("aaaaaaaa", "^a{2,}$").unwrap());
+        assert!(!match_pattern("a", "^a{2,}$").unwrap());
+        assert!(match_pattern("aaab", "a{1,3}ab").unwrap());
+    }
+
+    #[test]
+    fn bounded_repetition_malformed() {
+        assert!(Regex::parse("a{").is_err());
+        assert!(Regex::parse("a{x}").is_err());
+        assert!(Regex::parse("a{3,1}").is_err());
+    }
+
+    #[test]
+    fn literal() {
+        let regex = Regex::literal("a.c");
+        assert!(regex.is_match("a.c"));
+        assert!(!regex.is_match("abc"));
+        assert_eq!(regex.to_string(), "a\\.c");
+
+        let regex = Regex::literal("(1+2)*[3]$");
+        assert!(regex.is_match("x = (1+2)*[3]$;"));
+        assert!(!regex.is_match("3"));
+        assert!(Regex::literal("").is_match("anything"));
+    }
+
+    #[test]
+    fn whole_word() {
+        let regex = Regex::parse("cat").unwrap().whole_word();
+        assert!(regex.is_match("a cat"));
+        assert!(regex.is_match("cat, dog"));
+        assert!(!regex.is_match("category"));
+        assert!(!regex.is_match("bobcat"));
+        assert_eq!(regex.to_string(), "\\bcat\\b");
+
+        let regex = Regex::parse("cat|dog").unwrap().whole_word();
+        assert!(regex.is_match("hot dog"));
+        assert!(!regex.is_match("dogs and cats"));
+        assert_eq!(regex.find("cats cat").map(|m| m.range()), Some(5..8));
+        assert_eq!(regex.to_string(), "\\b(?:cat|dog)\\b");
+        assert_eq!(Regex::parse(&regex.to_string()).unwrap(), regex);
+    }
+
+    #[test]
+    fn whole_line() {
+        let regex = Regex::parse("\\d+").unwrap().whole_line();
+        assert!(regex.is_match("123"));
+        assert!(!regex.is_match("a123"));
+        assert!(!regex.is_match("123a"));
+        assert_eq!(regex.to_string(), "^\\d+$");
+
+        let regex = Regex::parse("cat|dog").unwrap().whole_line();
+        assert!(regex.is_match("dog"));
+        assert!(!regex.is_match("cat dog"));
+        assert!(!regex.is_match("cats"));
+        assert_eq!(regex.to_string(), "^(?:cat|dog)$");
+        assert_eq!(Regex::parse(&regex.to_string()).unwrap(), regex);
+
+        let regex = RegexBuilder::new()
+            .multi_line(true)
+            .build("b+")
+            .unwrap()
+            .whole_line();
+        assert_eq!(regex.find("abb\nbb\n").map(|m| m.range()), Some(4..6));
+    }
+
+    #[test]
+    fn matches_full() {
+        let regex = Regex::parse("\\d+").unwrap();
+        assert!(regex.matches_full("123"));
+        assert!(!regex.matches_full("12a"));
+        assert!(!regex.matches_full("a12"));
+        assert!(regex.is_match("12a"));
+
+        assert!(Regex::parse("^ab$").unwrap().matches_full("ab"));
+        assert!(Regex::parse("(a|ab)").unwrap().matches_full("ab"));
+        assert!(Regex::parse("a*").unwrap().matches_full(""));
+
+        let regex = RegexBuilder::new().multi_line(true).build("a").unwrap();
+        assert!(regex.matches_full("a"));
+        assert!(!regex.matches_full("a\nb"));
+        let regex = RegexBuilder::new().multi_line(true).build("^b$").unwrap();
+        assert!(!regex.matches_full("a\nb"));
+    }
+
+    #[test]
+    fn captures() {
+        let regex = Regex::parse("(\\d+)-(\\d+)").unwrap();
+        assert_eq!(
+            regex.captures("12-34"),
+            Some(vec![
+                Some("12-34".to_string()),
+                Some("12".to_string()),
+                Some("34".to_string())
+            ])
+        );
+        assert_eq!(regex.captures("12-"), None);
+
+        let regex = Regex::parse("(\\d)x").unwrap();
+        assert_eq!(
+            regex.captures("1y2x"),
+            Some(vec![Some("2x".to_string()), Some("2".to_string())])
+        );
+    }
+
+    #[test]
+    fn capture_spans() {
+        let regex = Regex::parse("(\\w+)@(\\w+)").unwrap();
+        assert_eq!(
+            regex.capture_spans("to me@host"),
+            Some(vec![Some((3, 10)), Some((3, 5)), Some((6, 10))])
+        );
+        assert_eq!(regex.capture_spans("nobody"), None);
+
+        let regex = Regex::parse("a(x)?(b)").unwrap();
+        assert_eq!(
+            regex.capture_spans("ab"),
+            Some(vec![Some((0, 2)), None, Some((1, 2))])
+        );
+    }
+
+    #[test]
+    fn named_groups() {
+        let regex = Regex::parse("(?<year>\\d{4})").unwrap();
+        assert_eq!(
+            regex.captures_named("2024"),
+            Some(HashMap::from([("year".to_string(), "2024".to_string())]))
+        );
+
+        let regex = Regex::parse("(?<user>\\w+)(@(?<host>\\w+))?").unwrap();
+        assert_eq!(regex.group_count, 3);
+        assert_eq!(
+            regex.captures_named("me@host"),
+            Some(HashMap::from([
+                ("user".to_string(), "me".to_string()),
+                ("host".to_string(), "host".to_string()),
+            ]))
+        );
+        assert_eq!(
+            regex.captures_named("me"),
+            Some(HashMap::from([("user".to_string(), "me".to_string())]))
+        );
+        assert_eq!(regex.captures_named("!"), None);
+        assert_eq!(regex.to_string(), "(?<user>\\w+)(@(?<host>\\w+))?");
+
+        assert_eq!(
+            Regex::parse("(?<a>x)(?<a>y)"),
+            Err(ParseError::DuplicateGroupName {
+                offset: 7,
+                name: "a".to_string()
+            })
+        );
+        assert_eq!(
+            Regex::parse("a(?<1x>y)"),
+            Err(ParseError::InvalidGroupName { offset: 1 })
+        );
+        assert!(Regex::parse("(?<a").is_err());
+    }
+
+    #[test]
+    fn backreference() {
+        assert!(match_pattern("cat and cat", "(cat) and \\1").unwrap());
+        assert!(!match_pattern("cat and dog", "(cat) and \\1").unwrap());
+        assert!(match_pattern(
+            "3 red squares and 3 red circles",
+            "(\\d+) (\\w+) squares and \\1 \\2 circles"
+        )
+        .unwrap());
+        assert!(!match_pattern(
+            "3 red squares and 4 red circles",
+            "(\\d+) (\\w+) squares and \\1 \\2 circles"
+        )
+        .unwrap());
+        assert!(!match_pattern("aa", "a\\1").unwrap());
+        assert!(!match_pattern("aa", "(a)\\2").unwrap());
+    }
+
+    #[test]
+    fn escaped_digits() {
+        assert!(match_pattern("a\0b", "a\\0b").unwrap());
+        assert!(!match_pattern("a0b", "a\\0b").unwrap());
+        assert_eq!(
+            Regex::parse("\\0").unwrap().patterns,
+            vec![Pattern::Character('\0')]
+        );
+
+        // Backreferences are a single digit, so `\10` is group 1 then `0`
+        assert_eq!(
+            Regex::parse("(a)\\10").unwrap().patterns[1..],
+            [Pattern::Backreference(1), Pattern::Character('0')]
+        );
+        assert!(match_pattern("aa0", "^(a)\\10$").unwrap());
+        assert!(!match_pattern("aaaaaaaaaaa", "(a)(a)(a)(a)(a)(a)(a)(a)(a)(a)\\10").unwrap());
+        assert_eq!(
+            Regex::parse("(a)\\10\\0").unwrap().to_string(),
+            "(a)\\10\\0"
+        );
+
+        assert!(match_pattern("a\0", "a[\\0]").unwrap());
+        assert!(!match_pattern("a0", "a[\\0]").unwrap());
+        assert!(match_pattern("\0\x05", "^[\\0-\\x08]+$").unwrap());
+        assert!(!match_pattern("\x05", "[^\\0-\\x08]").unwrap());
+    }
+
+    #[test]
+    fn empty_input() {
+        assert!(match_pattern("", "a?").unwrap());
+        assert!(match_pattern("", "a*").unwrap());
+        assert!(match_pattern("", "^$").unwrap());
+        assert!(!match_pattern("", "a").unwrap());
+        assert!(!match_pattern("", "a+").unwrap());
+    }
+
+    #[test]
+    fn empty_pattern() {
+        assert!(match_pattern("anything", "").unwrap());
+        assert!(match_pattern("", "").unwrap());
+
+        let regex = Regex::parse("").unwrap();
+        assert_eq!(regex.find("abc").map(|m| m.range()), Some(0..0));
+        assert_eq!(regex.captures(""), Some(vec![Some("".to_string())]));
+        assert!(regex.matches_full(""));
+        assert!(!regex.matches_full("a"));
+        assert_eq!(regex.find_iter("ab").count(), 3);
+        assert_eq!(regex.to_string(), "");
+        assert!(match_pattern("x", "(?i)").unwrap());
+    }
+
+    #[test]
+    fn match_at_end_of_input() {
+        assert!(match_pattern("abc", "x*$").unwrap());
+        assert!(match_pattern("abc", "\\b$").unwrap());
+        assert!(!match_pattern("abc", "\\B$").unwrap());
+    }
+
+    #[test]
+    fn quantified_groups() {
+        assert!(match_pattern("abab", "^(ab)+$").unwrap());
+        assert!(!match_pattern("abac", "^(ab)+$").unwrap());
+        assert!(match_pattern("catdogcat", "^(cat|dog)+$").unwrap());
+        assert!(match_pattern("ac", "^a(b)?c$").unwrap());
+        assert!(match_pattern("abc", "^a(b)?c$").unwrap());
+        assert!(match_pattern("", "^(ab)*$").unwrap());
+        assert!(match_pattern("ababab", "^(ab){3}$").unwrap());
+        assert!(!match_pattern("abab", "^(ab){3}$").unwrap());
+        assert!(match_pattern("ababa", "^(ab)+a$").unwrap());
+
+        let regex = Regex::parse("a(b)?(c|d)+").unwrap();
+        assert_eq!(
+            regex.captures("xacd"),
+            Some(vec![Some("acd".to_string()), None, Some("d".to_string())])
+        );
+    }
+
+    #[test]
+    fn match_pattern_finds_span() {
+        assert_eq!(match_pattern_find("ab12", "\\d+"), Ok(Some((2, 4))));
+        assert_eq!(match_pattern_find("abc", "\\d+"), Ok(None));
+        assert_eq!(match_pattern_find("", "x*"), Ok(Some((0, 0))));
+        assert_eq!(
+            match_pattern_find("ab", "a("),
+            Err(ParseError::UnterminatedAlternation { offset: 1 })
+        );
+    }
+
+    #[test]
+    fn wildcard() {
+        assert!(match_pattern("dog", "d.g").unwrap());
+        assert!(!match_pattern("cog", "d.g").unwrap());
+    }
+
+    #[test]
+    fn hex_escapes() {
+        assert!(match_pattern("a", "\\x61").unwrap());
+        assert!(match_pattern("A", "\\x41").unwrap());
+        assert!(!match_pattern("b", "\\x61").unwrap());
+        assert!(match_pattern("x\tyyy", "x\\x09y\\x79+").unwrap());
+        assert!(match_pattern("é", "\\xE9").unwrap());
+        assert!(match_pattern("a1", "\\x611").unwrap());
+    }
+
+    #[test]
+    fn unicode_escapes() {
+        assert!(match_pattern("☺", "\\u{263A}").unwrap());
+        assert!(match_pattern("hi 😀😀", "hi \\u{1F600}+$").unwrap());
+        assert!(match_pattern("a", "^\\u{61}$").unwrap());
+        assert!(!match_pattern("☹", "\\u{263a}").unwrap());
+        assert!(match_pattern("é", "\\u{00000e9}").unwrap());
+
+        assert!(match_pattern("☺", "^[\\u{263A}]$").unwrap());
+        assert!(!match_pattern("u", "[\\u{263A}]").unwrap());
+        assert!(!match_pattern("{", "[\\u{263A}]").unwrap());
+        assert!(match_pattern("β", "^[\\u{3B1}-\\u{3C9}]$").unwrap());
+        assert!(!match_pattern("b", "[\\u{3B1}-\\u{3C9}]").unwrap());
+        assert_eq!(
+            Regex::parse("[\\u{D800}]"),
+            Err(ParseError::InvalidEscape { offset: 1 })
+        );
+    }
+
+    #[test]
+    fn quantified_wildcard() {
+        assert!(match_pattern("anything", ".+").unwrap());
+        assert!(!match_pattern("", ".+").unwrap());
+        assert!(match_pattern("", ".?").unwrap());
+        assert!(match_pattern("", "^.*$").unwrap());
+        assert!(match_pattern("goøøøal", "^go.+al$").unwrap());
+        assert!(!match_pattern("gl", "^g.+l$").unwrap());
+        assert!(match_pattern("dg", "^d.?g$").unwrap());
+        assert!(match_pattern("a+", "^a.$").unwrap());
+        assert_eq!(Regex::parse("a.*b").unwrap().to_string(), "a.*b");
+    }
+
+    #[test]
+    fn dot_matches_newline() {
+        assert!(!match_pattern("a\nb", "a.b").unwrap());
+        assert!(match_pattern("a\nb", "(?s)a.b").unwrap());
+        assert!(!match_pattern("a\n", "^a.$").unwrap());
+
+        let flags = Flags {
+            dot_matches_newline: true,
+            ..Default::default()
+        };
+        let regex = Regex::parse_with_flags("a..b", flags).unwrap();
+        assert_eq!(regex.find("xa\n\nby").map(|m| m.range()), Some(1..5));
+        assert_eq!(regex.to_string(), "(?s)a..b");
+    }
+
+    #[test]
+    fn unicode_classes() {
+        assert!(!match_pattern("naïve", "^\\w+$").unwrap());
+        assert!(match_pattern("naïve", "(?u)^\\w+$").unwrap());
+        assert!(match_pattern("snake_case", "(?u)^\\w+$").unwrap());
+        assert!(match_pattern("٣", "(?u)^\\d$").unwrap());
+        assert!(!match_pattern("٣", "\\d").unwrap());
+        assert!(match_pattern("a\u{a0}b", "(?u)a\\sb").unwrap());
+        assert!(!match_pattern("a\u{a0}b", "a\\sb").unwrap());
+        assert!(match_pattern("é", "(?u)^[\\w]$").unwrap());
+        assert!(!match_pattern("é", "(?u)\\W").unwrap());
+
+        // Word boundaries use the same definition of a word character as `\w`
+        assert!(match_pattern(" é", "(?u)\\bé").unwrap());
+        assert!(!match_pattern(" é", "\\bé").unwrap());
+        assert!(!match_pattern("café", "(?u)caf\\b").unwrap());
+        assert!(match_pattern("café", "caf\\b").unwrap());
+        assert!(match_pattern("café", "(?u)caf\\B").unwrap());
+        assert!(match_pattern(" éé", "(?u)\\b(é)\\1").unwrap());
+        assert!(!match_pattern("aéé", "(?u)\\b(é)\\1").unwrap());
+
+        let regex = RegexBuilder::new().unicode(true).build("\\w+").unwrap();
+        assert_eq!(regex.find("¿qué?").map(|m| m.range()), Some(2..6));
+        assert_eq!(regex.to_string(), "(?u)\\w+");
+    }
+
+    #[test]
+    fn non_ascii_input() {
+        assert!(match_pattern("café", "caf.").unwrap());
+        assert!(match_pattern("café", "caf.$").unwrap());
+        assert!(match_pattern("naïve cat", "\\bcat").unwrap());
+        assert!(match_pattern("日本語 123", "\\d+$").unwrap());
+        assert!(match_pattern("ééé!", "^\\W+!").unwrap());
+        assert!(!match_pattern("über", "^u").unwrap());
+
+        let regex = Regex::parse("(\\w+)$").unwrap();
+        assert_eq!(
+            regex.captures("straße dog"),
+            Some(vec![Some("dog".to_string()), Some("dog".to_string())])
+        );
+    }
+
+    #[test]
+    fn alternation() {
+        assert!(match_pattern("dog", "(cat|dog)").unwrap());
+        assert!(match_pattern("cat", "(cat|dog)").unwrap());
+        assert!(!match_pattern("apple", "(cat|dog)").unwrap());
+        assert!(!match_pattern("cow", "(cat|dog)").unwrap());
+    }
+
+    #[test]
+    fn single_character_alternation() {
+        let alternation = Regex::parse("(a|b|c)").unwrap();
+        let group = Regex::parse("([abc])").unwrap();
+        for input in ["a", "b", "c", "d", "", "xcx", "ABC", "dcba"] {
+            assert_eq!(alternation.is_match(input), group.is_match(input));
+            assert_eq!(alternation.captures(input), group.captures(input));
+        }
+        assert_eq!(
+            alternation.patterns,
+            vec![Pattern::Group(
+                1,
+                None,
+                Box::new(Pattern::AnyOf(vec!['a', 'b', 'c']))
+            )]
+        );
+        assert_eq!(alternation.to_string(), "(a|b|c)");
+
+        let regex = Regex::parse("x|\\.|y").unwrap();
+        assert_eq!(regex.patterns, vec![Pattern::AnyOf(vec!['x', '.', 'y'])]);
+        assert_eq!(regex.to_string(), "x|\\.|y");
+        assert!(regex.is_match("."));
+        assert!(!regex.is_match("z"));
+
+        let regex = Regex::parse("(?i)^(a|b)+$").unwrap();
+        assert!(regex.is_match("aBbA"));
+        assert!(!regex.is_match("abc"));
+        assert!(matches!(
+            Regex::parse("a|bc").unwrap().patterns[..],
+            [Pattern::Alternation(_)]
+        ));
+    }
+
+    #[test]
+    fn alternatives_followed_by_rest() {
+        // Each alternative is followed by everything after the alternation,
+        // including the rest of any enclosing groups and repetitions
+        let regex = Regex::parse("((a|ab)(c|bcd))+(d*)$").unwrap();
+        assert_eq!(
+            regex.captures("abcdabcd"),
+            Some(vec![
+                Some("abcdabcd".to_string()),
+                Some("abcd".to_string()),
+                Some("a".to_string()),
+                Some("bcd".to_string()),
+                Some("".to_string()),
+            ])
+        );
+        assert!(Regex::parse("^(x(y|yz)|w)*z$").unwrap().is_match("xyzwxyz"));
+        assert!(!Regex::parse("^(x(y|yz)|w)+$").unwrap().is_match("xyzwz"));
+        assert_eq!(
+            Regex::parse("(cat|c)(at|x)\\2")
+                .unwrap()
+                .find("catxcatat")
+                .map(|m| m.range()),
+            Some(4..9)
+        );
+    }
+
+    #[test]
+    fn anchors_within_alternatives() {
+        // `$` asserts the end of the whole input wherever it appears, so an
+        // alternative ending with it can't be followed by anything more
+        let regex = Regex::parse("(foo$|bar)baz").unwrap();
+        assert!(!regex.is_match("foobaz"));
+        assert!(!regex.is_match("foo"));
+        assert!(regex.is_match("barbaz"));
+        assert_eq!(regex.captures("foobaz"), None);
+
+        let regex = Regex::parse("(foo$|bar)").unwrap();
+        assert_eq!(regex.find("foo bar").map(|m| m.range()), Some(4..7));
+        assert_eq!(regex.find("bar foo").map(|m| m.range()), Some(0..3));
+        assert_eq!(regex.find("xfoo").map(|m| m.range()), Some(1..4));
+
+        let regex = Regex::parse("(^foo|bar)baz").unwrap();
+        assert!(regex.is_match("foobaz"));
+        assert!(!regex.is_match("xfoobaz"));
+        assert!(regex.is_match("xbarbaz"));
+
+        let regex = Regex::parse("(?m)(foo$|bar)\\nbaz").unwrap();
+        assert!(regex.is_match("foo\nbaz"));
+    }
+
+    #[test]
+    fn anchored_alternation() {
+        let regex = Regex::parse("^(cat|dog)$").unwrap();
+        assert!(regex.is_match("cat"));
+        assert!(regex.is_match("dog"));
+        assert!(!regex.is_match("cats"));
+        assert!(!regex.is_match("hotdog"));
+        assert!(!regex.is_match("catdog"));
+        assert_eq!(
+            regex.captures("dog"),
+            Some(vec![Some("dog".to_string()); 2])
+        );
+        assert_eq!(regex.captures("dogs"), None);
+
+        // A backreference makes `is_match` backtrack rather than use the NFA
+        let regex = Regex::parse("^(cat|dog)$|\\1").unwrap();
+        assert!(regex.is_match("dog"));
+        assert!(!regex.is_match("dogs"));
+
+        let regex = Regex::parse("(?m)^(cat|dog)$").unwrap();
+        assert_eq!(
+            regex
+                .find_iter("cat\ncats\ndog")
+                .map(|m| m.range())
+                .collect::<Vec<_>>(),
+            vec![0..3, 9..12]
+        );
+    }
+
+    #[test]
+    fn alternatives_of_differing_lengths() {
+        let regex = Regex::parse("(a|ab)c").unwrap();
+        assert!(regex.is_match("abc"));
+        assert!(regex.is_match("ac"));
+        assert!(!regex.is_match("abbc"));
+        assert_eq!(
+            regex.captures("abc"),
+            Some(vec![Some("abc".to_string()), Some("ab".to_string())])
+        );
+        assert!(regex.matches_full("abc"));
+
+        let regex = Regex::parse("^(ab|a)(bc|c)$").unwrap();
+        assert_eq!(
+            regex.captures("abc"),
+            Some(vec![
+                Some("abc".to_string()),
+                Some("ab".to_string()),
+                Some("c".to_string())
+            ])
+        );
+        assert!(Regex::parse("^(a|ab)+c$").unwrap().is_match("abaabc"));
+        assert!(Regex::parse("x(a|ab)\\bc?").unwrap().is_match("xab"));
+    }
+}
@@ -1,466 +1,1042 @@
 use anyhow::Result;
-use std::{env, io, ops::Index, process};
+use grep_starter_rust::{Match, Regex};
+use std::{
+    cmp::Reverse,
+    env, fs,
+    io::{self, BufRead, Write},
+    path::Path,
+    process,
+};
 
-#[derive(Debug, PartialEq)]
-struct Regex<'regex> {
-    patterns: Vec<Pattern<'regex>>,
+/// Command line options.
+#[derive(Debug, Default, PartialEq)]
+struct Options {
+    /// The patterns to search for, given with `-E` or `-e`. A line matches if
+    /// any of them does.
+    patterns: Vec<String>,
+    /// Select lines that don't match any pattern (`-v`).
+    invert_match: bool,
+    /// Print the number of selected lines instead of the lines (`-c`).
+    count: bool,
+    /// Print only the matched parts of each line (`-o`).
+    only_matching: bool,
+    /// Prefix each printed line with its 1-based line number (`-n`).
+    line_number: bool,
+    /// Only match the patterns as whole words (`-w`).
+    word_regexp: bool,
+    /// Only match the patterns against whole lines (`-x`).
+    line_regexp: bool,
+    /// Treat the patterns as literal strings rather than regexes (`-F`).
+    fixed_strings: bool,
+    /// Separate records with NUL bytes rather than newlines, in both input
+    /// and output (`-z`).
+    null_data: bool,
+    /// Highlight matches with ANSI colors (`--color`).
+    color: bool,
+    /// Search directories recursively (`-r`).
+    recursive: bool,
+    /// Print only the names of files with a selected line (`-l`).
+    files_with_matches: bool,
+    /// Stop reading each input after this many selected lines (`-m`).
+    max_count: Option<usize>,
+    /// Files or directories to search, rather than standard input.
+    paths: Vec<String>,
 }
 
-#[derive(Debug, PartialEq, Clone)]
-enum Pattern<'regex> {
-    Character(char),
-    Digit,
-    Alphanumeric,
-    PositiveGroup(&'regex str),
-    NegativeGroup(&'regex str),
-    Start,
-    End,
-    OneOrMore(Box<Pattern<'regex>>),
-    ZeroOrOne(Box<Pattern<'regex>>),
-    Wildcard,
-    Alternation(Vec<Vec<Pattern<'regex>>>),
+/// Parse command line arguments, excluding the program name.
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Options> {
+    let mut options = Options::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-E" | "-e" => match args.next() {
+                Some(pattern) => options.patterns.push(pattern),
+                None => anyhow::bail!("expected a pattern after '{}'", arg),
+            },
+            "-v" => options.invert_match = true,
+            "-c" => options.count = true,
+            "-o" => options.only_matching = true,
+            "-n" => options.line_number = true,
+            "-w" => options.word_regexp = true,
+            "-x" => options.line_regexp = true,
+            "-F" => options.fixed_strings = true,
+            "-r" => options.recursive = true,
+            "-l" => options.files_with_matches = true,
+            "-m" => match args.next().and_then(|arg| arg.parse().ok()) {
+                Some(max_count) => options.max_count = Some(max_count),
+                None => anyhow::bail!("expected a number after '-m'"),
+            },
+            "-z" => options.null_data = true,
+            "--color" => options.color = true,
+            _ if arg.starts_with('-') => anyhow::bail!("unexpected argument '{}'", arg),
+            _ => options.paths.push(arg),
+        }
+    }
+    if options.patterns.is_empty() {
+        anyhow::bail!("expected a pattern given with '-E' or '-e'");
+    }
+    Ok(options)
 }
 
-impl<'regex> Pattern<'regex> {
-    fn parse(input: &'regex str) -> Result<(&'regex str, Self)> {
-        match input.chars().next().unwrap() {
-            '^' => {
-                // Start of string anchor
-                Ok((input.index(1..), Pattern::Start))
-            }
-            '$' => {
-                // End of string anchor
-                Ok((input.index(1..), Pattern::End))
-            }
-            '(' => {
-                // Alternation group
-                let mut current_pos = 0;
-                let mut start_of_current_alternative = 1;
-                let mut alternatives = Vec::new();
-                let mut chars = input.chars();
-                loop {
-                    match chars.next() {
-                        Some(ch) => match ch {
-                            '|' => {
-                                alternatives.push(
-                                    Regex::parse(
-                                        input.index(start_of_current_alternative..current_pos),
-                                    )?
-                                    .patterns,
-                                );
-                                current_pos += 1;
-                                start_of_current_alternative = current_pos;
-                            }
-                            ')' => {
-                                alternatives.push(
-                                    Regex::parse(
-                                        input.index(start_of_current_alternative..current_pos),
-                                    )?
-                                    .patterns,
-                                );
-                                break;
-                            }
-                            _ => {
-                                current_pos += 1;
-                            }
-                        },
-                        None => anyhow::bail!("premature end of alternation group"),
-                    }
-                }
+/// Parse each pattern given in `options`.
+fn build_regexes(options: &Options) -> Result<Vec<Regex>> {
+    options
+        .patterns
+        .iter()
+        .map(|pattern| build_regex(pattern, options))
+        .collect()
+}
 
-                Ok((
-                    input.index(current_pos + 1..),
-                    Pattern::Alternation(alternatives),
-                ))
-            }
-            '[' => {
-                // Character group
-                let (rest, is_negative) = if input.chars().nth(1) == Some('^') {
-                    (input.index(2..), true)
-                } else {
-                    (input.index(1..), false)
-                };
+/// Parse `pattern`, applying any options that change what it matches.
+fn build_regex(pattern: &str, options: &Options) -> Result<Regex> {
+    let mut regex = if options.fixed_strings {
+        Regex::literal(pattern)
+    } else {
+        Regex::parse(pattern)?
+    };
+    if options.word_regexp {
+        regex = regex.whole_word();
+    }
+    if options.line_regexp {
+        regex = regex.whole_line();
+    }
+    Ok(regex)
+}
 
-                let mut i = 0;
-                let mut chars = rest.chars();
-                loop {
-                    match chars.next() {
-                        Some(ch) => match ch {
-                            ']' => {
-                                break;
-                            }
-                            _ => {
-                                i += 1;
-                            }
-                        },
-                        None => {
-                            anyhow::bail!("premature end of character group")
-                        }
-                    }
-                }
+/// Remove a single trailing `\n` or `\r\n` from a line read from input.
+fn trim_line_ending(line: &str) -> &str {
+    let line = line.strip_suffix('\n').unwrap_or(line);
+    line.strip_suffix('\r').unwrap_or(line)
+}
 
-                let (rest, inner_pattern) = if is_negative {
-                    (
-                        rest.index(i + 1..),
-                        Pattern::NegativeGroup(rest.index(0..i)),
-                    )
-                } else {
-                    (
-                        rest.index(i + 1..),
-                        Pattern::PositiveGroup(rest.index(0..i)),
-                    )
-                };
+/// Read the next record of `reader` into `record`, replacing its contents and
+/// returning whether there was one. Records are lines, or with `-z` are ended
+/// by NUL bytes instead, and keep their terminator.
+fn read_record(reader: &mut impl BufRead, record: &mut String, options: &Options) -> Result<bool> {
+    record.clear();
+    if !options.null_data {
+        return Ok(reader.read_line(record)? > 0);
+    }
+    let mut bytes = Vec::new();
+    if reader.read_until(b'\0', &mut bytes)? == 0 {
+        return Ok(false);
+    }
+    record.push_str(std::str::from_utf8(&bytes)?);
+    Ok(true)
+}
 
-                if rest.starts_with('+') {
-                    Ok((rest.index(1..), Pattern::OneOrMore(Box::new(inner_pattern))))
-                } else if rest.starts_with('?') {
-                    Ok((rest.index(1..), Pattern::ZeroOrOne(Box::new(inner_pattern))))
-                } else {
-                    Ok((rest, inner_pattern))
-                }
-            }
-            '\\' => match input.chars().nth(1) {
-                Some('d') => {
-                    // Digit character class
-                    if input.chars().nth(2) == Some('+') {
-                        Ok((
-                            input.index(3..),
-                            Pattern::OneOrMore(Box::new(Pattern::Digit)),
-                        ))
-                    } else if input.chars().nth(2) == Some('?') {
-                        Ok((
-                            input.index(3..),
-                            Pattern::ZeroOrOne(Box::new(Pattern::Digit)),
-                        ))
-                    } else {
-                        Ok((input.index(2..), Pattern::Digit))
-                    }
-                    // Ok((input.index(2..), Pattern::Digit))
-                }
-                Some('w') => {
-                    // Alphanumeric character class
-                    if input.chars().nth(2) == Some('+') {
-                        Ok((
-                            input.index(3..),
-                            Pattern::OneOrMore(Box::new(Pattern::Alphanumeric)),
-                        ))
-                    } else if input.chars().nth(2) == Some('?') {
-                        Ok((
-                            input.index(3..),
-                            Pattern::ZeroOrOne(Box::new(Pattern::Alphanumeric)),
-                        ))
-                    } else {
-                        Ok((input.index(2..), Pattern::Alphanumeric))
-                    }
-                }
-                Some('\\') => Ok((input.index(2..), Pattern::Character('\\'))),
-                Some('$') => Ok((input.index(2..), Pattern::Character('$'))),
-                Some('^') => Ok((input.index(2..), Pattern::Character('^'))),
-                Some('+') => Ok((input.index(2..), Pattern::Character('+'))),
-                Some('?') => Ok((input.index(2..), Pattern::Character('?'))),
-                Some('.') => Ok((input.index(2..), Pattern::Character('.'))),
-                _ => {
-                    anyhow::bail!("unhandled pattern")
-                }
-            },
-            '.' => {
-                // Wildcard
-                Ok((input.index(1..), Pattern::Wildcard))
-            }
-            ch => {
-                // Single character
-                if input.chars().nth(1) == Some('+') {
-                    Ok((
-                        input.index(2..),
-                        Pattern::OneOrMore(Box::new(Pattern::Character(ch))),
-                    ))
-                } else if input.chars().nth(1) == Some('?') {
-                    Ok((
-                        input.index(2..),
-                        Pattern::ZeroOrOne(Box::new(Pattern::Character(ch))),
-                    ))
-                } else {
-                    Ok((input.index(1..), Pattern::Character(ch)))
-                }
-            }
-        }
+/// Remove the terminator from a record read by `read_record`.
+fn trim_record_ending<'a>(record: &'a str, options: &Options) -> &'a str {
+    if options.null_data {
+        record.strip_suffix('\0').unwrap_or(record)
+    } else {
+        trim_line_ending(record)
     }
+}
 
-    fn matches(&self, ch: char) -> bool {
-        match self {
-            Pattern::Character(c) => *c == ch,
-            Pattern::Digit => ch.is_ascii_digit(),
-            Pattern::Alphanumeric => ch.is_ascii_alphanumeric(),
-            Pattern::PositiveGroup(chars) => chars.contains(ch),
-            Pattern::NegativeGroup(chars) => !chars.contains(ch),
-            Pattern::Wildcard => true,
-            _ => unreachable!(),
+/// The terminator written after each output record.
+fn record_terminator(options: &Options) -> char {
+    if options.null_data {
+        '\0'
+    } else {
+        '\n'
+    }
+}
+
+/// Whether any of `regexes` matches `line`.
+fn matches_any(regexes: &[Regex], line: &str) -> Result<bool> {
+    for regex in regexes {
+        if regex.matches(line)? {
+            return Ok(true);
         }
     }
+    Ok(false)
 }
 
-impl<'regex> Regex<'regex> {
-    fn parse(input: &'regex str) -> Result<Self> {
-        // Only handle ascii patterns for simplicity
-        if input.chars().any(|ch| !ch.is_ascii()) {
-            anyhow::bail!("non-ascii character in pattern {}", input);
+/// The leftmost non-overlapping matches of any of `regexes` in `line`, in
+/// order. Of matches starting at the same offset, the longest is kept.
+fn find_matches<'t>(regexes: &'t [Regex], line: &'t str) -> Result<Vec<Match<'t>>> {
+    let mut matches = regexes
+        .iter()
+        .flat_map(|regex| regex.try_find_iter(line))
+        .collect::<Result<Vec<_>>>()?;
+    matches.sort_by_key(|found| (found.start(), Reverse(found.end())));
+    let mut last_end = 0;
+    matches.retain(|found| {
+        let keep = found.start() >= last_end;
+        if keep {
+            last_end = found.end();
         }
+        keep
+    });
+    Ok(matches)
+}
 
-        let mut patterns = Vec::new();
-        let mut rest = input;
-        while !rest.is_empty() {
-            let (remainder, pattern) = Pattern::parse(rest)?;
-            rest = remainder;
-            patterns.push(pattern);
+/// Whether `line` should be selected, given whether it matches any of
+/// `regexes`.
+fn is_selected(regexes: &[Regex], line: &str, options: &Options) -> Result<bool> {
+    Ok(matches_any(regexes, line)? != options.invert_match)
+}
+
+/// Whether another line can be selected once `selected` lines have been, or
+/// whether the `-m` limit has been reached.
+fn can_select_more(selected: usize, options: &Options) -> bool {
+    options.max_count != Some(selected)
+}
+
+/// Count the lines of `reader` selected by `regexes`, up to the `-m` limit.
+fn count_lines(mut reader: impl BufRead, regexes: &[Regex], options: &Options) -> Result<usize> {
+    let mut count = 0;
+    let mut line = String::new();
+    while can_select_more(count, options) && read_record(&mut reader, &mut line, options)? {
+        if is_selected(regexes, trim_record_ending(&line, options), options)? {
+            count += 1;
         }
-        Ok(Regex { patterns })
     }
+    Ok(count)
+}
 
-    fn matches(&self, input: &str) -> Result<bool> {
-        // Only handle ascii inputs for simplicity
-        if input.chars().any(|ch| !ch.is_ascii()) {
-            anyhow::bail!("non-ascii character in pattern {}", input);
+/// Whether any line of `reader` is selected by `regexes`, stopping at the first
+/// one that is.
+fn any_selected(mut reader: impl BufRead, regexes: &[Regex], options: &Options) -> Result<bool> {
+    let mut line = String::new();
+    while read_record(&mut reader, &mut line, options)? {
+        if is_selected(regexes, trim_record_ending(&line, options), options)? {
+            return Ok(true);
         }
-
-        Ok(self.match_(input, &self.patterns[..]))
     }
+    Ok(false)
+}
 
-    fn match_(&self, input: &str, patterns: &[Pattern]) -> bool {
-        if patterns.get(0) == Some(&Pattern::Start) {
-            return self.match_here(input, &patterns[1..]);
+/// Count the non-empty matches of `regexes` across every line of `reader`, for
+/// when only the matched parts of lines are selected. Only lines up to the
+/// `-m` limit of those with matches are counted.
+fn count_matches(mut reader: impl BufRead, regexes: &[Regex], options: &Options) -> Result<usize> {
+    let mut count = 0;
+    let mut lines = 0;
+    let mut line = String::new();
+    while can_select_more(lines, options) && read_record(&mut reader, &mut line, options)? {
+        let matches = find_matches(regexes, trim_record_ending(&line, options))?
+            .iter()
+            .filter(|found| !found.as_str().is_empty())
+            .count();
+        if matches > 0 {
+            count += matches;
+            lines += 1;
         }
+    }
+    Ok(count)
+}
 
-        let mut input = input;
-        loop {
-            if self.match_here(input, patterns) {
-                return true;
-            }
-            input = &input[1..];
-            if input.is_empty() {
+/// The ANSI escape codes that start and end highlighting of a match.
+const HIGHLIGHT_START: &str = "\x1b[31m";
+const HIGHLIGHT_END: &str = "\x1b[0m";
+
+/// Highlight the parts of `line` covered by `spans`, which are the start and
+/// end byte offsets of matches in any order. Overlapping or adjacent spans are
+/// highlighted as one, and empty spans are ignored.
+fn highlight(line: &str, spans: &[(usize, usize)]) -> String {
+    let mut spans = spans
+        .iter()
+        .copied()
+        .filter(|(start, end)| start < end)
+        .collect::<Vec<_>>();
+    spans.sort_unstable();
+
+    let mut output = String::with_capacity(line.len());
+    let mut last_end = 0;
+    let mut spans = spans.into_iter().peekable();
+    while let Some((start, mut end)) = spans.next() {
+        let start = start.max(last_end);
+        while let Some(&(next_start, next_end)) = spans.peek() {
+            if next_start > end {
                 break;
             }
+            end = end.max(next_end);
+            spans.next();
+        }
+        if start >= end {
+            continue;
         }
-        false
+        output.push_str(&line[last_end..start]);
+        output.push_str(HIGHLIGHT_START);
+        output.push_str(&line[start..end]);
+        output.push_str(HIGHLIGHT_END);
+        last_end = end;
     }
+    output.push_str(&line[last_end..]);
+    output
+}
 
-    fn match_here(&self, input: &str, patterns: &[Pattern]) -> bool {
-        match patterns.get(0) {
-            None => true,
-            Some(pattern) => match pattern {
-                Pattern::OneOrMore(inner_pattern) => {
-                    self.match_one_or_more(input, inner_pattern, &patterns[1..])
-                }
-                Pattern::ZeroOrOne(inner_pattern) => {
-                    self.match_zero_or_one(input, inner_pattern, &patterns[1..])
-                }
-                Pattern::Alternation(alternatives) => {
-                    self.match_alternatives(input, alternatives, &patterns[1..])
-                }
-                Pattern::End if patterns.get(1).is_none() => input.is_empty(),
-                Pattern::Character(ch) if input.starts_with(*ch) => {
-                    self.match_here(&input[1..], &patterns[1..])
-                }
-                pattern => {
-                    if let Some(ch) = input.chars().next() {
-                        if pattern.matches(ch) {
-                            self.match_here(&input[1..], &patterns[1..])
-                        } else {
-                            false
-                        }
-                    } else {
-                        false
-                    }
-                }
-            },
-        }
+/// Format `text` for output, prefixed with `filename` and `line_number` if
+/// given, as in `file.txt:3:text`.
+fn format_line(filename: Option<&str>, line_number: Option<usize>, text: &str) -> String {
+    let mut output = String::new();
+    if let Some(filename) = filename {
+        output.push_str(filename);
+        output.push(':');
     }
+    if let Some(line_number) = line_number {
+        output.push_str(&line_number.to_string());
+        output.push(':');
+    }
+    output.push_str(text);
+    output
+}
 
-    fn match_one_or_more(
-        &self,
-        input: &str,
-        inner_pattern: &Pattern,
-        next_patterns: &[Pattern],
-    ) -> bool {
-        let mut input = input;
-        while !input.is_empty() && inner_pattern.matches(input.chars().next().unwrap()) {
-            input = &input[1..];
-            if self.match_here(input, next_patterns) {
-                return true;
+/// Write each line of `reader` selected by `regexes` to `writer`, one line at a
+/// time up to the `-m` limit, returning whether any line was selected. Lines
+/// are prefixed with `filename` if given, and with their line number if
+/// requested.
+fn grep_lines(
+    mut reader: impl BufRead,
+    mut writer: impl Write,
+    regexes: &[Regex],
+    options: &Options,
+    filename: Option<&str>,
+) -> Result<bool> {
+    let mut selected = 0;
+    let mut line = String::new();
+    let mut line_number = 0;
+    let terminator = record_terminator(options);
+    while can_select_more(selected, options) && read_record(&mut reader, &mut line, options)? {
+        line_number += 1;
+        let text = trim_record_ending(&line, options);
+        if is_selected(regexes, text, options)? {
+            let line_number = options.line_number.then_some(line_number);
+            if !options.only_matching {
+                let highlighted;
+                let text = if options.color {
+                    let spans = find_matches(regexes, text)?
+                        .iter()
+                        .map(|found| (found.start(), found.end()))
+                        .collect::<Vec<_>>();
+                    highlighted = highlight(text, &spans);
+                    &highlighted
+                } else {
+                    text
+                };
+                let text = format_line(filename, line_number, text);
+                write!(writer, "{}{}", text, terminator)?;
+            } else if !options.invert_match {
+                // Inverted lines have no matches to print
+                for found in find_matches(regexes, text)?
+                    .into_iter()
+                    .filter(|found| !found.as_str().is_empty())
+                {
+                    let text = if options.color {
+                        highlight(found.as_str(), &[(0, found.as_str().len())])
+                    } else {
+                        found.as_str().to_string()
+                    };
+                    let text = format_line(filename, line_number, &text);
+                    write!(writer, "{}{}", text, terminator)?;
+                }
             }
+            selected += 1;
         }
-        false
     }
+    Ok(selected > 0)
+}
 
-    fn match_zero_or_one(
-        &self,
-        input: &str,
-        inner_pattern: &Pattern,
-        next_patterns: &[Pattern],
-    ) -> bool {
-        if self.match_here(input, next_patterns) {
-            return true;
+/// Search the lines of `reader`, writing either the selected lines, their
+/// count or with `-l` just `filename` to `writer`, and returning whether any
+/// line was selected. With `-o`, the count is of matches rather than lines.
+fn search(
+    reader: impl BufRead,
+    mut writer: impl Write,
+    regexes: &[Regex],
+    options: &Options,
+    filename: Option<&str>,
+) -> Result<bool> {
+    if options.files_with_matches {
+        let any_matched = any_selected(reader, regexes, options)?;
+        if any_matched {
+            let filename = filename.unwrap_or("(standard input)");
+            write!(writer, "{}{}", filename, record_terminator(options))?;
         }
-        if !input.is_empty() && inner_pattern.matches(input.chars().next().unwrap()) {
-            self.match_here(&input[1..], next_patterns)
+        Ok(any_matched)
+    } else if options.count {
+        let count = if options.only_matching && !options.invert_match {
+            count_matches(reader, regexes, options)?
         } else {
-            false
+            count_lines(reader, regexes, options)?
+        };
+        match filename {
+            Some(filename) => writeln!(writer, "{}:{}", filename, count)?,
+            None => writeln!(writer, "{}", count)?,
         }
+        Ok(count > 0)
+    } else {
+        grep_lines(reader, writer, regexes, options, filename)
     }
+}
 
-    fn match_alternatives(
-        &self,
-        input: &str,
-        alternatives: &[Vec<Pattern>],
-        next_patterns: &[Pattern],
-    ) -> bool {
-        for alternative in alternatives {
-            let mut alternative_patterns = Vec::new();
-            alternative_patterns.extend(alternative.iter().cloned());
-            alternative_patterns.extend(next_patterns.iter().cloned());
-            if self.match_here(input, &alternative_patterns) {
-                return true;
+/// Search the file at `path`, or every file beneath it if it's a directory and
+/// the search is recursive, returning whether any line was selected. Files that
+/// aren't valid UTF-8 are skipped. Files that can't be searched don't stop the
+/// search, but have an error message added to `errors`.
+fn search_path(
+    path: &Path,
+    writer: &mut impl Write,
+    regexes: &[Regex],
+    options: &Options,
+    with_filename: bool,
+    errors: &mut Vec<String>,
+) -> bool {
+    if path.is_dir() {
+        if !options.recursive {
+            errors.push(format!("{}: is a directory", path.display()));
+            return false;
+        }
+        let entries = fs::read_dir(path).and_then(|entries| {
+            entries
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<io::Result<Vec<_>>>()
+        });
+        let mut entries = match entries {
+            Ok(entries) => entries,
+            Err(error) => {
+                errors.push(format!("{}: {}", path.display(), error));
+                return false;
             }
+        };
+        entries.sort();
+
+        let mut any_matched = false;
+        for entry in entries {
+            any_matched |= search_path(&entry, writer, regexes, options, with_filename, errors);
+        }
+        return any_matched;
+    }
+
+    match search_file(path, writer, regexes, options, with_filename) {
+        Ok(matched) => matched,
+        Err(error) => {
+            errors.push(format!("{}: {}", path.display(), error));
+            false
         }
-        false
     }
 }
 
-fn match_pattern(input_line: &str, regex_str: &str) -> Result<bool> {
-    let regex = Regex::parse(regex_str)?;
-    regex.matches(input_line)
+/// Search the single file at `path`, returning whether any line was selected.
+fn search_file(
+    path: &Path,
+    writer: &mut impl Write,
+    regexes: &[Regex],
+    options: &Options,
+    with_filename: bool,
+) -> Result<bool> {
+    let contents = match String::from_utf8(fs::read(path)?) {
+        Ok(contents) => contents,
+        Err(_) => return Ok(false),
+    };
+    let filename = path.display().to_string();
+    search(
+        contents.as_bytes(),
+        writer,
+        regexes,
+        options,
+        (with_filename || options.files_with_matches).then_some(filename.as_str()),
+    )
 }
 
-// Usage: echo <input_text> | your_grep.sh -E <pattern>
-fn main() -> Result<()> {
-    if env::args().nth(1).unwrap() != "-E" {
-        println!("expected first argument to be '-E'");
-        process::exit(1);
+/// Whether output lines are prefixed with the file they came from, which they
+/// are when searching more than one file.
+fn with_filename(options: &Options) -> bool {
+    match &options.paths[..] {
+        [path] => options.recursive && Path::new(path).is_dir(),
+        paths => paths.len() > 1,
     }
+}
 
-    let pattern = env::args().nth(2).unwrap();
-    let mut input_line = String::new();
+// Usage: echo <input_text> | your_grep.sh [-v] [-c] [-o] [-n] [-w] [-x] [-F] [-z] [--color] [-l] [-m <num>] -E <pattern> [-e <pattern>]...
+//        your_grep.sh [-v] [-c] [-o] [-n] [-w] [-x] [-F] [-z] [--color] [-r] [-l] [-m <num>] -E <pattern> [-e <pattern>]... <path>...
+fn main() {
+    match run() {
+        Ok(code) => process::exit(code),
+        Err(error) => {
+            eprintln!("Error: {:?}", error);
+            process::exit(2)
+        }
+    }
+}
 
-    io::stdin().read_line(&mut input_line).unwrap();
+/// Run grep with the command line arguments, returning the exit code: 0 if
+/// any line was selected, 1 if none was, and 2 if an input couldn't be
+/// searched. Errors that stop the whole search, such as a bad pattern or a
+/// match that gave up, are returned instead, and should also exit with 2.
+fn run() -> Result<i32> {
+    let options = parse_args(env::args().skip(1))?;
+    let regexes = build_regexes(&options)?;
 
-    if match_pattern(&input_line, &pattern)? {
-        process::exit(0)
+    let mut errors = Vec::new();
+    let any_matched = if options.paths.is_empty() {
+        search(
+            io::stdin().lock(),
+            io::stdout().lock(),
+            &regexes,
+            &options,
+            None,
+        )?
     } else {
-        process::exit(1)
+        let with_filename = with_filename(&options);
+        let mut stdout = io::stdout().lock();
+        let mut any_matched = false;
+        for path in &options.paths {
+            any_matched |= search_path(
+                Path::new(path),
+                &mut stdout,
+                &regexes,
+                &options,
+                with_filename,
+                &mut errors,
+            );
+        }
+        any_matched
+    };
+
+    for error in &errors {
+        eprintln!("Error: {}", error);
+    }
+    if !errors.is_empty() {
+        Ok(2)
+    } else if any_matched {
+        Ok(0)
+    } else {
+        Ok(1)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{match_pattern, Pattern, Regex};
+    use super::{
+        build_regexes, count_lines, count_matches, format_line, grep_lines, highlight, matches_any,
+        parse_args, search, search_path, trim_line_ending, with_filename, Options,
+    };
+    use grep_starter_rust::Regex;
+    use std::{env, fs, path::PathBuf};
+
+    /// A scratch directory that's removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = env::temp_dir().join(format!("grep-test-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            TempDir(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
 
     #[test]
-    fn parse() {
-        let regex = Regex::parse("^[^abc]\\w?f+oo\\d+[bar]+(ca|d)$").unwrap();
+    fn parses_args() {
         assert_eq!(
-            regex,
-            Regex {
-                patterns: vec![
-                    Pattern::Start,
-                    Pattern::NegativeGroup("abc"),
-                    Pattern::ZeroOrOne(Box::new(Pattern::Alphanumeric)),
-                    Pattern::OneOrMore(Box::new(Pattern::Character('f'))),
-                    Pattern::Character('o'),
-                    Pattern::Character('o'),
-                    Pattern::OneOrMore(Box::new(Pattern::Digit)),
-                    Pattern::OneOrMore(Box::new(Pattern::PositiveGroup("bar"))),
-                    Pattern::Alternation(vec![
-                        vec![Pattern::Character('c'), Pattern::Character('a')],
-                        vec![Pattern::Character('d')],
-                    ]),
-                    Pattern::End
-                ]
+            parse_args(args(&["-E", "a+"])).unwrap(),
+            Options {
+                patterns: vec!["a+".to_string()],
+                ..Default::default()
             }
+        );
+        assert_eq!(
+            parse_args(args(&["-v", "-E", "a+"])).unwrap(),
+            Options {
+                patterns: vec!["a+".to_string()],
+                invert_match: true,
+                ..Default::default()
+            }
+        );
+        assert!(parse_args(args(&["-E"])).is_err());
+        assert!(parse_args(args(&["-v"])).is_err());
+        assert!(parse_args(args(&["-q", "-E", "a"])).is_err());
+    }
+
+    #[test]
+    fn matches_any_pattern() {
+        let options = parse_args(args(&["-e", "cat", "-e", "\\d+"])).unwrap();
+        assert_eq!(options.patterns, vec!["cat", "\\d+"]);
+        let regexes = build_regexes(&options).unwrap();
+        assert!(matches_any(&regexes, "a cat").unwrap());
+        assert!(matches_any(&regexes, "dog 42").unwrap());
+        assert!(!matches_any(&regexes, "dog").unwrap());
+        assert!(!matches_any(&[], "cat").unwrap());
+
+        let options = Options {
+            only_matching: true,
+            ..options
+        };
+        let mut output = Vec::new();
+        let matched = grep_lines(
+            "dog\n1 cat 23\n".as_bytes(),
+            &mut output,
+            &regexes,
+            &options,
+            None,
         )
+        .unwrap();
+        assert!(matched);
+        assert_eq!(String::from_utf8(output).unwrap(), "1\ncat\n23\n");
+        assert!(parse_args(args(&["-e"])).is_err());
     }
 
     #[test]
-    fn single_character() {
-        assert!(match_pattern("apple", "a").unwrap());
-        assert!(!match_pattern("dog", "a").unwrap());
+    fn builds_whole_word_regex() {
+        let options = parse_args(args(&["-w", "-E", "cat"])).unwrap();
+        let regexes = build_regexes(&options).unwrap();
+        assert!(matches_any(&regexes, "a cat").unwrap());
+        assert!(!matches_any(&regexes, "category").unwrap());
+
+        let options = parse_args(args(&["-E", "cat"])).unwrap();
+        assert!(matches_any(&build_regexes(&options).unwrap(), "category").unwrap());
     }
 
     #[test]
-    fn digit_character_class() {
-        assert!(match_pattern("3", "\\d").unwrap());
-        assert!(!match_pattern("c", "\\d").unwrap());
+    fn builds_fixed_string_regex() {
+        let options = parse_args(args(&["-F", "-E", "a.c"])).unwrap();
+        let regexes = build_regexes(&options).unwrap();
+        assert!(matches_any(&regexes, "a.c").unwrap());
+        assert!(!matches_any(&regexes, "abc").unwrap());
+
+        let options = parse_args(args(&["-F", "-E", "(a"])).unwrap();
+        assert!(matches_any(&build_regexes(&options).unwrap(), "(a)").unwrap());
     }
 
     #[test]
-    fn alphanumeric_character_class() {
-        assert!(match_pattern("foo101", "\\w").unwrap());
-        assert!(!match_pattern("$!?", "\\w").unwrap());
+    fn builds_whole_line_regex() {
+        let options = parse_args(args(&["-x", "-E", "\\d+"])).unwrap();
+        let regexes = build_regexes(&options).unwrap();
+        assert!(matches_any(&regexes, "123").unwrap());
+        assert!(!matches_any(&regexes, "a123").unwrap());
+
+        let options = parse_args(args(&["-E", "\\d+"])).unwrap();
+        assert!(matches_any(&build_regexes(&options).unwrap(), "a123").unwrap());
     }
 
     #[test]
-    fn positive_character_group() {
-        assert!(match_pattern("apple", "[abc]").unwrap());
-        assert!(!match_pattern("dog", "[abc]").unwrap());
+    fn trims_line_ending() {
+        assert_eq!(trim_line_ending("dog\n"), "dog");
+        assert_eq!(trim_line_ending("dog\r\n"), "dog");
+        assert_eq!(trim_line_ending("dog"), "dog");
+        assert_eq!(trim_line_ending("dog\n\n"), "dog\n");
+        assert_eq!(trim_line_ending("a\nb\n"), "a\nb");
+        assert_eq!(trim_line_ending("\n"), "");
     }
 
     #[test]
-    fn negative_character_group() {
-        assert!(match_pattern("dog", "[^abc]").unwrap());
-        assert!(!match_pattern("cab", "[^abc]").unwrap());
+    fn greps_lines() {
+        let regexes = [Regex::parse("dog$").unwrap()];
+        let mut output = Vec::new();
+        let matched = grep_lines(
+            "hot dog\ncat\nbulldog\r\ndogs\n".as_bytes(),
+            &mut output,
+            &regexes,
+            &Options::default(),
+            None,
+        )
+        .unwrap();
+        assert!(matched);
+        assert_eq!(String::from_utf8(output).unwrap(), "hot dog\nbulldog\n");
     }
 
     #[test]
-    fn combined_classes() {
-        assert!(match_pattern("1 apple", "\\d apple").unwrap());
-        assert!(!match_pattern("1 orange", "\\d apple").unwrap());
+    fn greps_lines_without_match() {
+        let regexes = [Regex::parse("\\d").unwrap()];
+        let mut output = Vec::new();
+        let matched = grep_lines(
+            "cat\ndog".as_bytes(),
+            &mut output,
+            &regexes,
+            &Options::default(),
+            None,
+        )
+        .unwrap();
+        assert!(!matched);
+        assert!(output.is_empty());
+    }
 
-        assert!(match_pattern("100 apples", "\\d\\d\\d apple").unwrap());
-        assert!(!match_pattern("1 apple", "\\d\\d\\d apple").unwrap());
+    #[test]
+    fn greps_lines_inverted() {
+        let regexes = [Regex::parse("\\d").unwrap()];
+        let options = Options {
+            invert_match: true,
+            ..Default::default()
+        };
 
-        assert!(match_pattern("3 dogs", "\\d \\w\\w\\ws").unwrap());
-        assert!(match_pattern("4 cats", "\\d \\w\\w\\ws").unwrap());
-        assert!(!match_pattern("1 dog", "\\d \\w\\w\\ws").unwrap());
+        let mut output = Vec::new();
+        let matched = grep_lines(
+            "a1\nb\nc2\nd\n".as_bytes(),
+            &mut output,
+            &regexes,
+            &options,
+            None,
+        )
+        .unwrap();
+        assert!(matched);
+        assert_eq!(String::from_utf8(output).unwrap(), "b\nd\n");
 
-        assert!(!match_pattern("sally has 12 apples", "\\d\\\\d\\\\d apples").unwrap());
+        let mut output = Vec::new();
+        let matched =
+            grep_lines("1\n2\n".as_bytes(), &mut output, &regexes, &options, None).unwrap();
+        assert!(!matched);
+        assert!(output.is_empty());
     }
 
     #[test]
-    fn start_anchor() {
-        assert!(match_pattern("log", "^log").unwrap());
-        assert!(!match_pattern("slog", "^log").unwrap());
+    fn greps_only_matching() {
+        let regexes = [Regex::parse("\\d+").unwrap()];
+        let options = Options {
+            only_matching: true,
+            ..Default::default()
+        };
+
+        let mut output = Vec::new();
+        let matched = grep_lines(
+            "a12b345\nnone\n6\n".as_bytes(),
+            &mut output,
+            &regexes,
+            &options,
+            None,
+        )
+        .unwrap();
+        assert!(matched);
+        assert_eq!(String::from_utf8(output).unwrap(), "12\n345\n6\n");
+
+        let mut output = Vec::new();
+        let matched =
+            grep_lines("none\n".as_bytes(), &mut output, &regexes, &options, None).unwrap();
+        assert!(!matched);
+        assert!(output.is_empty());
+
+        // Repeated groups match however long the input is
+        let regexes = [Regex::parse("(ab)+").unwrap()];
+        let input = "ab".repeat(100_000);
+        let mut output = Vec::new();
+        let matched = grep_lines(input.as_bytes(), &mut output, &regexes, &options, None).unwrap();
+        assert!(matched);
+        assert_eq!(String::from_utf8(output).unwrap(), input + "\n");
+
+        // A pattern too long for the backtracking matcher is reported as an
+        // error rather than overflowing the stack
+        let pattern = format!("(a){}\\1", "a?".repeat(5_000));
+        let regexes = [Regex::parse(&pattern).unwrap()];
+        let error = grep_lines("aa".as_bytes(), Vec::new(), &regexes, &options, None).unwrap_err();
+        assert_eq!(error.to_string(), "match recursed too deeply and gave up");
     }
 
     #[test]
-    fn end_anchor() {
-        assert!(match_pattern("dog", "dog$").unwrap());
-        assert!(!match_pattern("dogs", "dog$").unwrap());
+    fn stops_at_max_count() {
+        let options = parse_args(args(&["-m", "2", "-E", "\\d"])).unwrap();
+        assert_eq!(options.max_count, Some(2));
+        let regexes = build_regexes(&options).unwrap();
+        let input = ["a1", "b2", "c", "d3", "e4", "f5"].join("\n");
+
+        let mut output = Vec::new();
+        let matched = grep_lines(input.as_bytes(), &mut output, &regexes, &options, None).unwrap();
+        assert!(matched);
+        assert_eq!(String::from_utf8(output).unwrap(), "a1\nb2\n");
+        assert_eq!(
+            count_lines(input.as_bytes(), &regexes, &options).unwrap(),
+            2
+        );
+        assert_eq!(
+            count_matches(input.as_bytes(), &regexes, &options).unwrap(),
+            2
+        );
+
+        let options = Options {
+            max_count: Some(0),
+            ..options
+        };
+        let mut output = Vec::new();
+        assert!(!grep_lines(input.as_bytes(), &mut output, &regexes, &options, None).unwrap());
+        assert!(output.is_empty());
+        assert!(parse_args(args(&["-m", "x", "-E", "a"])).is_err());
     }
 
     #[test]
-    fn one_or_more() {
-        assert!(match_pattern("apple", "a+").unwrap());
-        assert!(match_pattern("SaaS", "a+").unwrap());
-        assert!(!match_pattern("dog", "a+").unwrap());
+    fn formats_lines() {
+        assert_eq!(format_line(None, None, "dog"), "dog");
+        assert_eq!(format_line(None, Some(3), "dog"), "3:dog");
+        assert_eq!(format_line(Some("a.txt"), None, "dog"), "a.txt:dog");
+        assert_eq!(format_line(Some("a.txt"), Some(12), ""), "a.txt:12:");
     }
 
     #[test]
-    fn zero_or_one() {
-        assert!(match_pattern("dogs", "dogs?").unwrap());
-        assert!(match_pattern("dog", "dogs?").unwrap());
-        assert!(!match_pattern("cat", "dogs?").unwrap());
-        assert!(!match_pattern("cag", "ca?t").unwrap());
+    fn greps_lines_with_line_numbers() {
+        let regexes = [Regex::parse("\\d").unwrap()];
+        let input = "a1\nb\nc2\nd\n";
+        let mut options = Options {
+            line_number: true,
+            ..Default::default()
+        };
+
+        let mut output = Vec::new();
+        grep_lines(input.as_bytes(), &mut output, &regexes, &options, None).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "1:a1\n3:c2\n");
+
+        options.invert_match = true;
+        let mut output = Vec::new();
+        grep_lines(input.as_bytes(), &mut output, &regexes, &options, Some("f")).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "f:2:b\nf:4:d\n");
     }
 
     #[test]
-    fn wildcard() {
-        assert!(match_pattern("dog", "d.g").unwrap());
-        assert!(!match_pattern("cog", "d.g").unwrap());
+    fn highlights_spans() {
+        assert_eq!(highlight("a cat", &[(2, 5)]), "a \x1b[31mcat\x1b[0m");
+        assert_eq!(
+            highlight("a1b22", &[(1, 2), (3, 5)]),
+            "a\x1b[31m1\x1b[0mb\x1b[31m22\x1b[0m"
+        );
+        assert_eq!(highlight("abcd", &[(2, 4), (0, 2)]), "\x1b[31mabcd\x1b[0m");
+        assert_eq!(highlight("abcd", &[(0, 3), (1, 2)]), "\x1b[31mabc\x1b[0md");
+        assert_eq!(highlight("abcd", &[(1, 3), (2, 4)]), "a\x1b[31mbcd\x1b[0m");
+        assert_eq!(highlight("abcd", &[(1, 1)]), "abcd");
+        assert_eq!(highlight("abcd", &[]), "abcd");
     }
 
     #[test]
-    fn alternation() {
-        assert!(match_pattern("dog", "(cat|dog)").unwrap());
-        assert!(match_pattern("cat", "(cat|dog)").unwrap());
-        assert!(!match_pattern("apple", "(cat|dog)").unwrap());
-        assert!(!match_pattern("cow", "(cat|dog)").unwrap());
+    fn greps_lines_with_color() {
+        let regexes = [Regex::parse("\\d+").unwrap()];
+        let mut options = Options {
+            color: true,
+            ..Default::default()
+        };
+
+        let mut output = Vec::new();
+        grep_lines(
+            "a1b22\nc\n".as_bytes(),
+            &mut output,
+            &regexes,
+            &options,
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "a\x1b[31m1\x1b[0mb\x1b[31m22\x1b[0m\n"
+        );
+
+        options.only_matching = true;
+        let mut output = Vec::new();
+        grep_lines("a1b22\n".as_bytes(), &mut output, &regexes, &options, None).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "\x1b[31m1\x1b[0m\n\x1b[31m22\x1b[0m\n"
+        );
+    }
+
+    #[test]
+    fn greps_null_separated_records() {
+        let regexes = [Regex::parse("^a\\nb$").unwrap()];
+        let options = Options {
+            null_data: true,
+            ..Default::default()
+        };
+        let input = "a\nb\0a\nc\0ab\n";
+
+        let mut output = Vec::new();
+        let matched = grep_lines(input.as_bytes(), &mut output, &regexes, &options, None).unwrap();
+        assert!(matched);
+        assert_eq!(String::from_utf8(output).unwrap(), "a\nb\0");
+
+        let regexes = [Regex::parse("a").unwrap()];
+        assert_eq!(
+            count_lines(input.as_bytes(), &regexes, &options).unwrap(),
+            3
+        );
+        let regexes = [Regex::parse("\\n").unwrap()];
+        assert_eq!(
+            count_lines(input.as_bytes(), &regexes, &options).unwrap(),
+            3
+        );
+        assert_eq!(
+            count_lines(input.as_bytes(), &regexes, &Options::default()).unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn counts_lines() {
+        let regexes = [Regex::parse("\\d").unwrap()];
+        let input = "1\na\n22\nb\nc3\n";
+        assert_eq!(
+            count_lines(input.as_bytes(), &regexes, &Options::default()).unwrap(),
+            3
+        );
+
+        let options = Options {
+            invert_match: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            count_lines(input.as_bytes(), &regexes, &options).unwrap(),
+            2
+        );
+        assert_eq!(count_lines("".as_bytes(), &regexes, &options).unwrap(), 0);
+    }
+
+    #[test]
+    fn counts_only_matching() {
+        let regexes = [Regex::parse("\\d").unwrap()];
+        let input = "a1b2\nc3\nd\n";
+        assert_eq!(
+            count_matches(input.as_bytes(), &regexes, &Options::default()).unwrap(),
+            3
+        );
+        assert_eq!(
+            count_lines(input.as_bytes(), &regexes, &Options::default()).unwrap(),
+            2
+        );
+
+        let options = Options {
+            count: true,
+            only_matching: true,
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        assert!(search(input.as_bytes(), &mut output, &regexes, &options, None).unwrap());
+        assert_eq!(String::from_utf8(output).unwrap(), "3\n");
+
+        let regexes = [Regex::parse("x*").unwrap()];
+        assert_eq!(
+            count_matches(input.as_bytes(), &regexes, &Options::default()).unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn searches_directories_recursively() {
+        let dir = TempDir::new("recursive");
+        fs::create_dir_all(dir.0.join("nested")).unwrap();
+        fs::write(dir.0.join("a.txt"), "cat\ndog\n").unwrap();
+        fs::write(dir.0.join("nested/b.txt"), "hotdog\n").unwrap();
+        fs::write(dir.0.join("nested/c.txt"), "cat\n").unwrap();
+        fs::write(dir.0.join("binary"), [0x64, 0x6f, 0x67, 0xff]).unwrap();
+
+        let regexes = [Regex::parse("dog").unwrap()];
+        let options = Options {
+            recursive: true,
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        let mut errors = Vec::new();
+        let matched = search_path(&dir.0, &mut output, &regexes, &options, true, &mut errors);
+        assert!(matched);
+        assert!(errors.is_empty());
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            format!(
+                "{}:dog\n{}:hotdog\n",
+                dir.0.join("a.txt").display(),
+                dir.0.join("nested/b.txt").display()
+            )
+        );
+
+        let regexes = [Regex::parse("fish").unwrap()];
+        let mut output = Vec::new();
+        let matched = search_path(&dir.0, &mut output, &regexes, &options, true, &mut errors);
+        assert!(!matched);
+        assert!(output.is_empty());
+        assert!(errors.is_empty());
+
+        let options = Options::default();
+        let mut output = Vec::new();
+        assert!(!search_path(
+            &dir.0,
+            &mut output,
+            &regexes,
+            &options,
+            true,
+            &mut errors
+        ));
+        assert_eq!(errors, [format!("{}: is a directory", dir.0.display())]);
+    }
+
+    #[test]
+    fn reports_errors_per_file() {
+        let dir = TempDir::new("errors");
+        fs::write(dir.0.join("a.txt"), "dog\n").unwrap();
+
+        let regexes = [Regex::parse("dog").unwrap()];
+        let options = Options::default();
+        let mut output = Vec::new();
+        let mut errors = Vec::new();
+        let missing = dir.0.join("missing.txt");
+        for path in [&missing, &dir.0.join("a.txt")] {
+            search_path(path, &mut output, &regexes, &options, true, &mut errors);
+        }
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            format!("{}:dog\n", dir.0.join("a.txt").display())
+        );
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with(&format!("{}: ", missing.display())));
+    }
+
+    #[test]
+    fn prefixes_filenames_for_several_files() {
+        let dir = TempDir::new("with-filename");
+        let file = dir.0.join("a.txt");
+        fs::write(&file, "dog\n").unwrap();
+        let options = |recursive, paths: &[&PathBuf]| Options {
+            recursive,
+            paths: paths
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect(),
+            ..Default::default()
+        };
+
+        assert!(!with_filename(&options(false, &[&file])));
+        assert!(!with_filename(&options(true, &[&file])));
+        assert!(with_filename(&options(true, &[&dir.0])));
+        assert!(with_filename(&options(false, &[&file, &file])));
+    }
+
+    #[test]
+    fn lists_files_with_matches() {
+        let dir = TempDir::new("files-with-matches");
+        fs::write(dir.0.join("a.txt"), "dog\nhotdog\n").unwrap();
+        fs::write(dir.0.join("b.txt"), "cat\n").unwrap();
+        fs::write(dir.0.join("c.txt"), "cat\ndogs\n").unwrap();
+
+        let regexes = [Regex::parse("dog").unwrap()];
+        let options = Options {
+            recursive: true,
+            files_with_matches: true,
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        let matched = search_path(
+            &dir.0,
+            &mut output,
+            &regexes,
+            &options,
+            true,
+            &mut Vec::new(),
+        );
+        assert!(matched);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            format!(
+                "{}\n{}\n",
+                dir.0.join("a.txt").display(),
+                dir.0.join("c.txt").display()
+            )
+        );
+
+        let mut output = Vec::new();
+        assert!(search("dog\n".as_bytes(), &mut output, &regexes, &options, None).unwrap());
+        assert_eq!(String::from_utf8(output).unwrap(), "(standard input)\n");
     }
 }
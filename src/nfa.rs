@@ -0,0 +1,370 @@
+use std::sync::Mutex;
+
+use crate::{is_word_boundary, Flags, Pattern};
+
+/// A Thompson NFA compiled from a regex, which matches in time linear in the
+/// length of the input by tracking every active state at once instead of
+/// backtracking.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Nfa {
+    flags: Flags,
+    nodes: Vec<Node>,
+    start: usize,
+    scratch: Scratch,
+}
+
+/// The state sets used while running an NFA, kept between runs so that
+/// matching many inputs doesn't allocate for each one.
+#[derive(Debug, Default)]
+struct Buffers {
+    current: Vec<usize>,
+    next: Vec<usize>,
+    /// The position at which each state was last added, to add it only once.
+    added: Vec<usize>,
+}
+
+/// Buffers shared by every run of an NFA. A run that finds them in use on
+/// another thread allocates its own rather than waiting.
+#[derive(Debug, Default)]
+struct Scratch(Mutex<Buffers>);
+
+impl Clone for Scratch {
+    fn clone(&self) -> Self {
+        Scratch::default()
+    }
+}
+
+/// Scratch space holds no part of the NFA itself, so any two are equal.
+impl PartialEq for Scratch {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+/// Input an NFA can run over, as characters at increasing positions.
+trait Haystack {
+    /// The character starting at `position`, if any.
+    fn char_at(&self, position: usize) -> Option<char>;
+
+    /// The character ending at `position`, if any.
+    fn char_before(&self, position: usize) -> Option<char>;
+
+    /// The position after the character `ch` starting at `position`.
+    fn advance(&self, position: usize, ch: char) -> usize;
+}
+
+impl Haystack for str {
+    fn char_at(&self, position: usize) -> Option<char> {
+        self[position..].chars().next()
+    }
+
+    fn char_before(&self, position: usize) -> Option<char> {
+        self[..position].chars().next_back()
+    }
+
+    fn advance(&self, position: usize, ch: char) -> usize {
+        position + ch.len_utf8()
+    }
+}
+
+/// Bytes, where each byte is a single Latin-1 character.
+impl Haystack for [u8] {
+    fn char_at(&self, position: usize) -> Option<char> {
+        self.get(position).map(|&byte| char::from(byte))
+    }
+
+    fn char_before(&self, position: usize) -> Option<char> {
+        self[..position].last().map(|&byte| char::from(byte))
+    }
+
+    fn advance(&self, position: usize, _ch: char) -> usize {
+        position + 1
+    }
+}
+
+/// A single state of an NFA, holding the index of the state(s) that follow it.
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    /// Consume a character matched by a single-character pattern.
+    Char(Pattern, usize),
+    /// Continue from both states.
+    Split(usize, usize),
+    /// Continue only if a zero-width anchor holds at the current position.
+    Assert(Pattern, usize),
+    Match,
+}
+
+impl Nfa {
+    /// Compile `patterns` to an NFA matching with `flags`, or `None` if they
+    /// use backreferences or possessive quantifiers, which an NFA can't
+    /// express.
+    pub(crate) fn compile(patterns: &[Pattern], flags: Flags) -> Option<Self> {
+        let mut nfa = Nfa {
+            flags,
+            nodes: vec![Node::Match],
+            start: 0,
+            scratch: Scratch::default(),
+        };
+        nfa.start = nfa.compile_sequence(patterns, 0)?;
+        Some(nfa)
+    }
+
+    /// Compile `patterns` to states leading to `next`, returning the first.
+    fn compile_sequence(&mut self, patterns: &[Pattern], next: usize) -> Option<usize> {
+        patterns
+            .iter()
+            .rev()
+            .try_fold(next, |next, pattern| self.compile_pattern(pattern, next))
+    }
+
+    fn compile_pattern(&mut self, pattern: &Pattern, next: usize) -> Option<usize> {
+        let node = match pattern {
+            Pattern::Start
+            | Pattern::End
+            | Pattern::StringStart
+            | Pattern::StringEnd
+            | Pattern::WordBoundary
+            | Pattern::NonWordBoundary => self.push(Node::Assert(pattern.clone(), next)),
+            Pattern::OneOrMore(inner) => return self.compile_repeat(inner, 1, None, next),
+            Pattern::ZeroOrOne(inner) => return self.compile_repeat(inner, 0, Some(1), next),
+            Pattern::ZeroOrMore(inner) => return self.compile_repeat(inner, 0, None, next),
+            Pattern::Repeat { inner, min, max } => {
+                return self.compile_repeat(inner, *min, *max, next)
+            }
+            Pattern::Alternation(alternatives) => {
+                let mut starts = alternatives
+                    .iter()
+                    .map(|alternative| self.compile_sequence(alternative, next))
+                    .collect::<Option<Vec<_>>>()?;
+                let mut node = starts.pop()?;
+                while let Some(start) = starts.pop() {
+                    node = self.push(Node::Split(start, node));
+                }
+                node
+            }
+            // Laziness changes which match is found but not whether there is one
+            Pattern::Group(_, _, inner) | Pattern::Lazy(inner) => {
+                return self.compile_pattern(inner, next)
+            }
+            Pattern::GroupEnd(_) | Pattern::CollectEnd | Pattern::RecordEnd => next,
+            // Neither can be expressed without backtracking
+            Pattern::Backreference(_) | Pattern::Possessive(_) => return None,
+            pattern => self.push(Node::Char(pattern.clone(), next)),
+        };
+        Some(node)
+    }
+
+    /// Compile between `min` and `max` repetitions of `inner`, by chaining
+    /// `min` required copies onto either a loop or `max - min` optional ones.
+    fn compile_repeat(
+        &mut self,
+        inner: &Pattern,
+        min: usize,
+        max: Option<usize>,
+        next: usize,
+    ) -> Option<usize> {
+        let mut node = match max {
+            Some(max) => {
+                let mut node = next;
+                for _ in min..max {
+                    let start = self.compile_pattern(inner, node)?;
+                    node = self.push(Node::Split(start, next));
+                }
+                node
+            }
+            None => {
+                // The loop's first branch is filled in once the inner pattern
+                // has been compiled to lead back to it
+                let split = self.push(Node::Split(next, next));
+                let start = self.compile_pattern(inner, split)?;
+                self.nodes[split] = Node::Split(start, next);
+                split
+            }
+        };
+        for _ in 0..min {
+            node = self.compile_pattern(inner, node)?;
+        }
+        Some(node)
+    }
+
+    fn push(&mut self, node: Node) -> usize {
+        self.nodes.push(node);
+        self.nodes.len() - 1
+    }
+
+    /// Whether the NFA matches anywhere in `input`.
+    pub fn is_match(&self, input: &str) -> bool {
+        self.run(input)
+    }
+
+    /// Whether the NFA matches anywhere in `input`, treating each byte as a
+    /// Latin-1 character.
+    pub fn is_match_bytes(&self, input: &[u8]) -> bool {
+        self.run(input)
+    }
+
+    fn run<H: Haystack + ?Sized>(&self, input: &H) -> bool {
+        match self.scratch.0.try_lock() {
+            Ok(mut buffers) => self.run_with(input, &mut buffers),
+            Err(_) => self.run_with(input, &mut Buffers::default()),
+        }
+    }
+
+    fn run_with<H: Haystack + ?Sized>(&self, input: &H, buffers: &mut Buffers) -> bool {
+        let Buffers {
+            current,
+            next,
+            added,
+        } = buffers;
+        current.clear();
+        next.clear();
+        added.clear();
+        added.resize(self.nodes.len(), usize::MAX);
+        let mut position = 0;
+        loop {
+            // Start a new thread at every position, for an unanchored search
+            if self.add(current, added, self.start, input, position) {
+                return true;
+            }
+            let ch = match input.char_at(position) {
+                Some(ch) => ch,
+                None => return false,
+            };
+            let after = input.advance(position, ch);
+            for &node in current.iter() {
+                if let Node::Char(pattern, out) = &self.nodes[node] {
+                    if self.flags.matches_char(pattern, ch)
+                        && self.add(next, added, *out, input, after)
+                    {
+                        return true;
+                    }
+                }
+            }
+            std::mem::swap(current, next);
+            next.clear();
+            position = after;
+        }
+    }
+
+    /// Add `node` and every state reachable from it without consuming input
+    /// to `states`, returning whether the match state is reachable.
+    fn add<H: Haystack + ?Sized>(
+        &self,
+        states: &mut Vec<usize>,
+        added: &mut [usize],
+        node: usize,
+        input: &H,
+        position: usize,
+    ) -> bool {
+        if added[node] == position {
+            return false;
+        }
+        added[node] = position;
+        match &self.nodes[node] {
+            Node::Char(..) => {
+                states.push(node);
+                false
+            }
+            Node::Split(first, second) => {
+                self.add(states, added, *first, input, position)
+                    || self.add(states, added, *second, input, position)
+            }
+            Node::Assert(pattern, out) => {
+                self.holds(pattern, input, position)
+                    && self.add(states, added, *out, input, position)
+            }
+            Node::Match => true,
+        }
+    }
+
+    /// Whether the zero-width anchor `pattern` holds at `position`.
+    fn holds<H: Haystack + ?Sized>(&self, pattern: &Pattern, input: &H, position: usize) -> bool {
+        let (previous, next) = (input.char_before(position), input.char_at(position));
+        match pattern {
+            Pattern::Start => self.flags.at_start(previous),
+            Pattern::End => self.flags.at_end(next),
+            Pattern::StringStart => previous.is_none(),
+            Pattern::StringEnd => next.is_none(),
+            Pattern::WordBoundary => is_word_boundary(previous, next, self.flags.unicode),
+            Pattern::NonWordBoundary => !is_word_boundary(previous, next, self.flags.unicode),
+            _ => unreachable!(),
+        }
+    }
+
+    /// The leftmost start offset at which the NFA matches `input`, along with
+    /// the nearest and furthest offsets that a match from there can end at.
+    pub(crate) fn match_ends(&self, input: &str) -> Option<(usize, usize, usize)> {
+        // Each thread is a state along with the offset its match started at,
+        // kept in order of that offset so that a state reached from several
+        // starts keeps only the leftmost
+        let mut current = Vec::new();
+        let mut next = Vec::new();
+        let mut added = vec![usize::MAX; self.nodes.len()];
+        let mut ends = None;
+        let mut position = 0;
+        loop {
+            // Any match starting here would be to the right of one already found
+            if ends.is_none() {
+                let thread = (self.start, position);
+                self.add_thread(&mut current, &mut added, thread, input, position, &mut ends);
+            } else if current.is_empty() {
+                break;
+            }
+            let ch = match input.char_at(position) {
+                Some(ch) => ch,
+                None => break,
+            };
+            let after = input.advance(position, ch);
+            for &(node, start) in current.iter() {
+                if matches!(ends, Some((leftmost, _, _)) if start > leftmost) {
+                    break;
+                }
+                if let Node::Char(pattern, out) = &self.nodes[node] {
+                    if self.flags.matches_char(pattern, ch) {
+                        let thread = (*out, start);
+                        self.add_thread(&mut next, &mut added, thread, input, after, &mut ends);
+                    }
+                }
+            }
+            std::mem::swap(&mut current, &mut next);
+            next.clear();
+            position = after;
+        }
+        ends
+    }
+
+    /// Like `add`, but for a thread of `match_ends` that started at `start`,
+    /// recording in `ends` where it reaches the match state instead of
+    /// stopping there.
+    fn add_thread(
+        &self,
+        threads: &mut Vec<(usize, usize)>,
+        added: &mut [usize],
+        (node, start): (usize, usize),
+        input: &str,
+        position: usize,
+        ends: &mut Option<(usize, usize, usize)>,
+    ) {
+        if added[node] == position {
+            return;
+        }
+        added[node] = position;
+        match &self.nodes[node] {
+            Node::Char(..) => threads.push((node, start)),
+            Node::Split(first, second) => {
+                self.add_thread(threads, added, (*first, start), input, position, ends);
+                self.add_thread(threads, added, (*second, start), input, position, ends);
+            }
+            Node::Assert(pattern, out) => {
+                if self.holds(pattern, input, position) {
+                    self.add_thread(threads, added, (*out, start), input, position, ends);
+                }
+            }
+            Node::Match => match ends {
+                Some((leftmost, _, longest_end)) if *leftmost == start => *longest_end = position,
+                Some((leftmost, _, _)) if *leftmost < start => {}
+                _ => *ends = Some((start, position, position)),
+            },
+        }
+    }
+}
@@ -0,0 +1,354 @@
+use crate::{escaped_char, next_class_item, posix_class, ClassItem, Flags, ParseError, Pattern};
+use std::{iter::Peekable, str::CharIndices};
+
+/// A recursive descent parser over the characters of a pattern. Slices of the
+/// pattern are only ever taken at the offsets of its characters, so patterns
+/// may contain any Unicode characters.
+pub(crate) struct Parser<'regex> {
+    input: &'regex str,
+    chars: Peekable<CharIndices<'regex>>,
+    /// The names of the named groups parsed so far.
+    group_names: Vec<&'regex str>,
+}
+
+impl<'regex> Parser<'regex> {
+    pub(crate) fn new(input: &'regex str) -> Self {
+        Parser {
+            input,
+            chars: input.char_indices().peekable(),
+            group_names: Vec::new(),
+        }
+    }
+
+    /// The byte offset of the next unparsed character.
+    pub(crate) fn offset(&mut self) -> usize {
+        self.chars.peek().map_or(self.input.len(), |&(i, _)| i)
+    }
+
+    /// The unparsed remainder of the pattern.
+    pub(crate) fn rest(&mut self) -> &'regex str {
+        let offset = self.offset();
+        &self.input[offset..]
+    }
+
+    /// Consume the characters before byte offset `offset`.
+    pub(crate) fn skip_to(&mut self, offset: usize) {
+        while self.offset() < offset {
+            self.chars.next();
+        }
+    }
+
+    fn peek_char(&mut self) -> Option<char> {
+        self.chars.peek().map(|&(_, ch)| ch)
+    }
+
+    /// Parse inline flags such as `(?im)` at the start of the pattern into
+    /// `flags`. Several groups of flags, as in `(?i)(?m)`, are the same as one
+    /// group with all of them.
+    pub(crate) fn parse_flags(&mut self, flags: &mut Flags) -> Result<(), ParseError> {
+        loop {
+            let rest = self.rest();
+            if !rest.starts_with("(?") || rest.starts_with("(?<") || rest.starts_with("(?:") {
+                return Ok(());
+            }
+            let offset = self.offset();
+            self.skip_to(offset + "(?".len());
+            // A group needs at least one flag
+            if let Some((offset, flag)) = self.chars.next_if(|&(_, ch)| ch == ')') {
+                return Err(ParseError::UnknownFlag { offset, flag });
+            }
+            loop {
+                match self.chars.next() {
+                    Some((_, 'i')) => flags.case_insensitive = true,
+                    Some((_, 'm')) => flags.multiline = true,
+                    Some((_, 's')) => flags.dot_matches_newline = true,
+                    Some((_, 'u')) => flags.unicode = true,
+                    Some((_, ')')) => break,
+                    Some((offset, flag)) => return Err(ParseError::UnknownFlag { offset, flag }),
+                    None => return Err(ParseError::UnterminatedFlags { offset }),
+                }
+            }
+        }
+    }
+
+    /// Parse a whole regex, which may be a top-level alternation.
+    pub(crate) fn parse_regex(&mut self) -> Result<Vec<Pattern>, ParseError> {
+        let mut alternatives = self.parse_alternatives(false)?;
+        if alternatives.len() == 1 {
+            Ok(alternatives.pop().unwrap())
+        } else {
+            Ok(vec![alternation(alternatives)])
+        }
+    }
+
+    /// Parse one or more sequences of patterns separated by `|`.
+    fn parse_alternatives(&mut self, in_group: bool) -> Result<Vec<Vec<Pattern>>, ParseError> {
+        let mut alternatives = vec![self.parse_sequence(in_group)?];
+        while self.chars.next_if(|&(_, ch)| ch == '|').is_some() {
+            alternatives.push(self.parse_sequence(in_group)?);
+        }
+        Ok(alternatives)
+    }
+
+    /// Parse patterns until the end of the pattern, the `|` ending the current
+    /// alternative or, inside a group, the closing `)`.
+    fn parse_sequence(&mut self, in_group: bool) -> Result<Vec<Pattern>, ParseError> {
+        let mut patterns = Vec::new();
+        while let Some(ch) = self.peek_char() {
+            if ch == '|' || (in_group && ch == ')') {
+                break;
+            }
+            patterns.push(self.parse_pattern()?);
+        }
+        Ok(patterns)
+    }
+
+    /// Parse a single pattern, along with any quantifier following it.
+    pub(crate) fn parse_pattern(&mut self) -> Result<Pattern, ParseError> {
+        let (offset, ch) = self.chars.next().ok_or(ParseError::MissingPattern {
+            offset: self.input.len(),
+        })?;
+        let pattern = match ch {
+            '^' => {
+                // Start of string anchor
+                return Ok(Pattern::Start);
+            }
+            '$' => {
+                // End of string anchor
+                return Ok(Pattern::End);
+            }
+            '(' => {
+                // Capture group, containing one or more alternatives. A `(?:`
+                // group only groups them, without capturing
+                let capturing = !self.rest().starts_with("?:");
+                let name = if !capturing {
+                    self.skip_to(offset + "(?:".len());
+                    None
+                } else if self.rest().starts_with("?<") {
+                    Some(self.parse_group_name(offset)?)
+                } else if self.peek_char() == Some('?') {
+                    return Err(ParseError::MisplacedFlags { offset });
+                } else {
+                    None
+                };
+
+                let alternatives = self.parse_alternatives(true)?;
+                if self.chars.next_if(|&(_, ch)| ch == ')').is_none() {
+                    return Err(ParseError::UnterminatedAlternation { offset });
+                }
+
+                if capturing {
+                    // Groups are numbered once the whole regex has been parsed
+                    Pattern::Group(
+                        0,
+                        name.map(String::from),
+                        Box::new(alternation(alternatives)),
+                    )
+                } else {
+                    alternation(alternatives)
+                }
+            }
+            ')' => {
+                // Groups stop before their closing parenthesis, so this one
+                // has no opening parenthesis
+                return Err(ParseError::UnmatchedParen { offset });
+            }
+            '[' => {
+                // Character group. A `]` straight after the opening `[` or
+                // `[^` is a literal rather than closing the group, as groups
+                // can't be empty
+                let is_negative = self.chars.next_if(|&(_, ch)| ch == '^').is_some();
+                let start = self.offset();
+                self.chars.next_if(|&(_, ch)| ch == ']');
+                let end = loop {
+                    match self.chars.next() {
+                        Some((i, '\\')) => {
+                            // Skip the escape, after checking that it's valid
+                            if let Some((_, ch)) = self.chars.next() {
+                                let (_, len) = escaped_char(ch, self.rest())
+                                    .ok_or(ParseError::InvalidEscape { offset: i })?;
+                                let end = self.offset() + len;
+                                self.skip_to(end);
+                            }
+                        }
+                        Some((i, '[')) if self.peek_char() == Some(':') => {
+                            // POSIX class such as `[:digit:]`
+                            let name_start = i + "[:".len();
+                            if let Some(len) = self.input[name_start..].find(":]") {
+                                let name = &self.input[name_start..name_start + len];
+                                if posix_class(name).is_none() {
+                                    return Err(ParseError::UnknownClass {
+                                        offset: i,
+                                        name: name.to_string(),
+                                    });
+                                }
+                                self.skip_to(name_start + len + ":]".len());
+                            }
+                        }
+                        Some((i, ']')) => break i,
+                        Some(_) => {}
+                        None => return Err(ParseError::UnterminatedGroup { offset }),
+                    }
+                };
+
+                // A range that ends before it starts could never match
+                let mut items = self.input[start..end].chars();
+                loop {
+                    let item_offset = end - items.as_str().len();
+                    match next_class_item(&mut items) {
+                        Some(ClassItem::Range(first, last)) if first > last => {
+                            return Err(ParseError::InvalidRange {
+                                offset: item_offset,
+                            });
+                        }
+                        Some(_) => {}
+                        None => break,
+                    }
+                }
+
+                // Only a leading `^` negates the group
+                let chars = self.input[start..end].to_string();
+                if is_negative {
+                    Pattern::NegativeGroup(chars)
+                } else {
+                    Pattern::PositiveGroup(chars)
+                }
+            }
+            '\\' => match self.chars.next().map(|(_, ch)| ch) {
+                // Character classes
+                Some('d') => Pattern::Digit,
+                Some('w') => Pattern::Alphanumeric,
+                Some('D') => Pattern::NonDigit,
+                Some('W') => Pattern::NonAlphanumeric,
+                Some('s') => Pattern::Whitespace,
+                Some('S') => Pattern::NonWhitespace,
+                Some('A') => return Ok(Pattern::StringStart),
+                Some('z') => return Ok(Pattern::StringEnd),
+                Some('b') => return Ok(Pattern::WordBoundary),
+                Some('B') => return Ok(Pattern::NonWordBoundary),
+                Some(ch @ '1'..='9') => {
+                    // Backreference to a capture group. Only one digit is
+                    // read, so `\10` is group 1 followed by a literal `0`
+                    let index = ch.to_digit(10).unwrap() as usize;
+                    Pattern::Backreference(index)
+                }
+                Some(ch) => {
+                    let (ch, len) = escaped_char(ch, self.rest())
+                        .ok_or(ParseError::InvalidEscape { offset })?;
+                    let end = self.offset() + len;
+                    self.skip_to(end);
+                    Pattern::Character(ch)
+                }
+                None => return Err(ParseError::TrailingBackslash { offset }),
+            },
+            '.' => {
+                // Wildcard
+                Pattern::Wildcard
+            }
+            '+' | '?' | '*' => {
+                // A quantifier where a pattern to repeat should be, such as
+                // at the start or straight after another quantifier
+                return Err(ParseError::NothingToRepeat { offset });
+            }
+            ch => {
+                // Single character
+                Pattern::Character(ch)
+            }
+        };
+        self.parse_quantifier(pattern)
+    }
+
+    /// Parse the `?<name>` of a named group opened at `offset`.
+    fn parse_group_name(&mut self, offset: usize) -> Result<&'regex str, ParseError> {
+        let start = self.offset() + "?<".len();
+        let name = self.input[start..]
+            .split_once('>')
+            .map(|(name, _)| name)
+            .filter(|name| {
+                name.starts_with(|ch: char| ch.is_ascii_alphabetic() || ch == '_')
+                    && name
+                        .chars()
+                        .all(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+            })
+            .ok_or(ParseError::InvalidGroupName { offset })?;
+        if self.group_names.contains(&name) {
+            return Err(ParseError::DuplicateGroupName {
+                offset,
+                name: name.to_string(),
+            });
+        }
+        self.group_names.push(name);
+        self.skip_to(start + name.len() + ">".len());
+        Ok(name)
+    }
+
+    /// Wrap `pattern` in a quantifier if one follows it. A `?` after the
+    /// quantifier makes it lazy, and a `+` makes it possessive.
+    fn parse_quantifier(&mut self, pattern: Pattern) -> Result<Pattern, ParseError> {
+        let offset = self.offset();
+        let pattern = match self.peek_char() {
+            Some('+') => Pattern::OneOrMore(Box::new(pattern)),
+            Some('?') => Pattern::ZeroOrOne(Box::new(pattern)),
+            Some('*') => Pattern::ZeroOrMore(Box::new(pattern)),
+            Some('{') => {
+                // Bounded repetition
+                let invalid_repetition = ParseError::InvalidRepetition { offset };
+                let rest = self.rest();
+                let end = rest.find('}').ok_or(invalid_repetition.clone())?;
+                let bounds = &rest[1..end];
+                let (min, max) = match bounds.split_once(',') {
+                    Some((min, "")) => (parse_bound(min), None),
+                    Some((min, max)) => (parse_bound(min), Some(parse_bound(max))),
+                    None => (parse_bound(bounds), Some(parse_bound(bounds))),
+                };
+                let (min, max) = match (min, max) {
+                    (Some(min), None) => (min, None),
+                    (Some(min), Some(Some(max))) if min <= max => (min, Some(max)),
+                    _ => return Err(invalid_repetition),
+                };
+                self.skip_to(offset + end);
+                Pattern::Repeat {
+                    inner: Box::new(pattern),
+                    min,
+                    max,
+                }
+            }
+            _ => return Ok(pattern),
+        };
+        self.chars.next();
+        match self.peek_char() {
+            Some('?') => {
+                self.chars.next();
+                Ok(Pattern::Lazy(Box::new(pattern)))
+            }
+            Some('+') => {
+                self.chars.next();
+                Ok(Pattern::Possessive(Box::new(pattern)))
+            }
+            _ => Ok(pattern),
+        }
+    }
+}
+
+/// An alternation of `alternatives`, or the equivalent `AnyOf` if there are
+/// several and each is a single character.
+fn alternation(alternatives: Vec<Vec<Pattern>>) -> Pattern {
+    let chars = alternatives
+        .iter()
+        .map(|alternative| match alternative[..] {
+            [Pattern::Character(ch)] => Some(ch),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>();
+    match chars {
+        Some(chars) if chars.len() > 1 => Pattern::AnyOf(chars),
+        _ => Pattern::Alternation(alternatives),
+    }
+}
+
+fn parse_bound(input: &str) -> Option<usize> {
+    if !input.chars().all(|ch| ch.is_ascii_digit()) {
+        return None;
+    }
+    input.parse().ok()
+}